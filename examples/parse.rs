@@ -1,51 +1,51 @@
-use modit::{Event, Parser, ViParser, BACKSPACE, DELETE, ESCAPE};
-use std::io::{self, Read, Write};
-use termion::{event::Key, input::TermRead, raw::IntoRawMode};
-
-fn parse(string: &str) -> Vec<Event> {
+use modit::{Event, Key, KeyCode, Modifiers, Parser, ViParser};
+use std::{
+    env,
+    io::{self, Write},
+};
+use termion::{event::Key as TermionKey, input::TermRead, raw::IntoRawMode};
+
+fn parse(keys: &str) -> Vec<Event> {
     let mut parser = ViParser::new();
     let mut events = Vec::new();
     //TODO: what to do with selection
     let selection = false;
-    for c in string.chars() {
-        parser.parse(c, selection, |event| events.push(event));
-    }
+    parser.parse_keys(keys, selection, |event| events.push(event));
     events
 }
 
 fn main() {
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
 
+    // Parse keys in vim notation from arguments, like `iHello, World!<Esc>` or `diw`
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        for arg in args {
+            println!("{}: {:#?}", arg, parse(&arg));
+        }
+        return;
+    }
+
     let mut stdout = io::stdout().into_raw_mode().unwrap();
-    let mut stdin = io::stdin();
+    let stdin = io::stdin();
 
     let mut parser = ViParser::new();
     for key_res in stdin.keys() {
-        let key = key_res.unwrap();
-        write!(stdout, "{:?}\r\n", key).unwrap();
-        let c = match key {
-            Key::Backspace => BACKSPACE,
-            Key::Ctrl('c') => break,
-            Key::Char(c) => c,
-            Key::Delete => DELETE,
-            Key::Esc => ESCAPE,
+        let (code, modifiers) = match key_res.unwrap() {
+            TermionKey::Backspace => (KeyCode::Backspace, Modifiers::NONE),
+            TermionKey::Ctrl('c') => break,
+            TermionKey::Ctrl(c) => (KeyCode::Char(c), Modifiers::CTRL),
+            TermionKey::Alt(c) => (KeyCode::Char(c), Modifiers::ALT),
+            TermionKey::Char(c) => (KeyCode::Char(c), Modifiers::NONE),
+            TermionKey::Delete => (KeyCode::Delete, Modifiers::NONE),
+            TermionKey::Esc => (KeyCode::Escape, Modifiers::NONE),
             _ => continue,
         };
-        parser.parse(c, false, |event| {
+        let key = Key::new(code, modifiers);
+        write!(stdout, "{}\r\n", key).unwrap();
+        parser.parse(key, false, |event| {
             write!(stdout, "  {:?}\r\n", event).unwrap();
             stdout.flush().unwrap();
         });
     }
-
-    /*
-    println!("{:#?}", parse(&format!("iHello, World!{ESCAPE}")));
-
-    println!("{:#?}", parse(&format!("10w")));
-
-    println!("{:#?}", parse(&format!("cw")));
-
-    println!("{:#?}", parse(&format!("diw")));
-
-    println!("{:#?}", parse(&format!("dap")));
-    */
 }
@@ -1,11 +1,24 @@
-use modit::{Event, Key, Motion, Parser, ViMode, ViParser};
+use modit::{
+    Event, Key, KeyCode, Modifiers, Motion, MouseAction, Parser, ScrollPosition, TerminalDecoder,
+    TerminalInput, ViMode, ViParser, ViPending, WriteRequest,
+};
 use std::{
     env, fs,
-    io::{self, Write},
-};
-use termion::{
-    event::Key as TermionKey, input::TermRead, raw::IntoRawMode, screen::IntoAlternateScreen,
+    io::{self, Read, Write},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
 };
+use termion::{raw::IntoRawMode, screen::IntoAlternateScreen};
+
+/// Screen cells between tab stops
+const TAB_WIDTH: usize = 8;
+
+/// Longest time between clicks of a double or triple click
+const MULTI_CLICK_TIME: Duration = Duration::from_millis(500);
+
+/// Time to wait for the rest of an escape sequence, like vim's `ttimeoutlen`
+const ESCAPE_TIME: Duration = Duration::from_millis(50);
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Cursor {
@@ -31,9 +44,62 @@ struct Editor {
     height: usize,
     redraw: bool,
     scroll: LayoutCursor,
+    /// Characters replaced in replace mode, None if appended
+    replaced: Vec<Option<char>>,
+    path: Option<String>,
+    quit: bool,
 }
 
 impl Editor {
+    pub fn write(&mut self, cursor: Cursor, request: &WriteRequest) -> io::Result<()> {
+        let path = match request.path.as_ref().or(self.path.as_ref()) {
+            Some(path) => path.clone(),
+            None => return Err(io::Error::other("no file name")),
+        };
+        let (start, end) = match request.range {
+            Some(range) => {
+                let resolve = |address: modit::ExAddress| {
+                    address
+                        .resolve(cursor.line + 1, self.lines.len(), |_mark| None)
+                        .ok_or_else(|| io::Error::other("invalid range"))
+                };
+                let start = resolve(range.start)?;
+                let end = match range.end {
+                    Some(end) => resolve(end)?,
+                    None => start,
+                };
+                (start.saturating_sub(1), end)
+            }
+            None => (0, self.lines.len()),
+        };
+        let mut data = String::new();
+        for line in self.lines.get(start..end).unwrap_or_default() {
+            data.push_str(line);
+            data.push('\n');
+        }
+        //TODO: only write if modified when requested
+        fs::write(&path, data)?;
+        if request.save_as || self.path.is_none() {
+            self.path = Some(path);
+        }
+        Ok(())
+    }
+
+    /// Number of screen cells used by a Tab at cursor
+    pub fn tab_cells(&self, cursor: Cursor) -> usize {
+        let col = self
+            .lines
+            .get(cursor.line)
+            .and_then(|line| line.get(..cursor.index))
+            .map_or(0, |before| {
+                before.chars().fold(0, |col, c| match c {
+                    '\t' => col + TAB_WIDTH - col % TAB_WIDTH,
+                    _ => col + unicode_width::UnicodeWidthChar::width(c).unwrap_or(0),
+                })
+            });
+        TAB_WIDTH - col % TAB_WIDTH
+    }
+
     pub fn delete_char(&mut self, cursor: &mut Cursor) -> Result<Option<char>, InsertError> {
         let line = self
             .lines
@@ -142,6 +208,24 @@ impl Editor {
         })
     }
 
+    /// Cursor for the screen cell at row and col, or the last character before it on the
+    /// same row
+    pub fn mouse_cursor(&self, row: usize, col: usize) -> Option<Cursor> {
+        let row = row.saturating_add(self.scroll.row);
+        let col = col.saturating_add(self.scroll.col);
+        let mut found = None;
+        self.layout(|cursor, layout_cursor, _c| {
+            if layout_cursor.row > row {
+                return Some(());
+            }
+            if layout_cursor.row == row && (found.is_none() || layout_cursor.col <= col) {
+                found = Some(cursor);
+            }
+            None
+        });
+        found
+    }
+
     pub fn motion(&mut self, mut cursor: Cursor, motion: Motion) -> Option<Cursor> {
         match motion {
             Motion::Down => {
@@ -160,10 +244,7 @@ impl Editor {
             }
             Motion::Left | Motion::LeftInLine => {
                 let line = self.lines.get(cursor.line)?;
-                match line
-                    .get(..cursor.index)
-                    .and_then(|x| x.chars().rev().next())
-                {
+                match line.get(..cursor.index).and_then(|x| x.chars().next_back()) {
                     Some(c) => {
                         cursor.index = cursor.index.checked_sub(c.len_utf8())?;
                         return Some(cursor);
@@ -196,6 +277,13 @@ impl Editor {
                     self.scroll.row = max_scroll_row;
                 }
             }
+            Motion::Position { line, index } => {
+                let line_str = self.lines.get(line)?;
+                if !line_str.is_char_boundary(index) {
+                    return None;
+                }
+                return Some(Cursor { line, index });
+            }
             Motion::PageUp => {
                 self.scroll.row = self
                     .scroll
@@ -286,6 +374,27 @@ impl Editor {
             return Err(err);
         }
 
+        if let Some(completion) = &parser.completion {
+            write!(
+                w,
+                "{}{}",
+                termion::cursor::Goto(1, (self.height as u16).saturating_sub(1)),
+                termion::clear::CurrentLine
+            )?;
+            for (i, candidate) in completion.candidates.iter().enumerate() {
+                if completion.selected == Some(i) {
+                    write!(
+                        w,
+                        "{}{candidate}{}  ",
+                        termion::style::Invert,
+                        termion::style::Reset
+                    )?;
+                } else {
+                    write!(w, "{candidate}  ")?;
+                }
+            }
+        }
+
         write!(w, "{}", termion::cursor::Goto(1, self.height as u16))?;
         match &parser.mode {
             ViMode::Normal => {
@@ -295,41 +404,46 @@ impl Editor {
                 write!(w, "-- INSERT --")?;
             }
             ViMode::Extra(extra) => {
-                write!(w, "{}{}", parser.cmd, extra)?;
+                write!(w, "{}", parser.cmd)?;
+                match char::from_u32(u32::from(*extra) | 0x40) {
+                    // Show control characters like ^W
+                    Some(c) if extra.is_ascii_control() => write!(w, "^{}", c)?,
+                    _ => write!(w, "{}", extra)?,
+                }
             }
             ViMode::Replace => {
                 write!(w, "-- REPLACE --")?;
             }
+            ViMode::VirtualReplace => {
+                write!(w, "-- VREPLACE --")?;
+            }
             ViMode::Visual => {
                 write!(w, "-- VISUAL -- {}", parser.cmd)?;
             }
             ViMode::VisualLine => {
                 write!(w, "-- VISUAL LINE -- {}", parser.cmd)?;
             }
-            ViMode::Command { value } => {
-                write!(
-                    w,
-                    ":{value}{} {}",
-                    termion::style::Invert,
-                    termion::style::Reset
-                )?;
+            ViMode::Command { value, cursor } => {
+                write!(w, ":")?;
+                draw_cmdline(w, value, *cursor)?;
             }
-            ViMode::Search { value, forwards } => {
-                if *forwards {
-                    write!(
-                        w,
-                        "/{value}{} {}",
-                        termion::style::Invert,
-                        termion::style::Reset
-                    )?;
-                } else {
-                    write!(
-                        w,
-                        "?{value}{} {}",
-                        termion::style::Invert,
-                        termion::style::Reset
-                    )?;
-                }
+            ViMode::Search {
+                value,
+                cursor,
+                forwards,
+            } => {
+                write!(w, "{}", if *forwards { '/' } else { '?' })?;
+                draw_cmdline(w, value, *cursor)?;
+            }
+        }
+
+        if let Some(pending) = parser.pending {
+            match pending {
+                ViPending::Digraph(first) => write!(w, " {}", first.unwrap_or('?'))?,
+                ViPending::Literal { prefix, .. } => match prefix {
+                    Some(prefix) => write!(w, " ^{prefix}")?,
+                    None => write!(w, " ^")?,
+                },
             }
         }
 
@@ -337,10 +451,24 @@ impl Editor {
     }
 }
 
+fn draw_cmdline<W: Write>(w: &mut W, value: &str, cursor: usize) -> io::Result<()> {
+    let (before, after) = value.split_at(cursor);
+    let mut after_chars = after.chars();
+    let c = after_chars.next().unwrap_or(' ');
+    write!(
+        w,
+        "{before}{}{c}{}{}",
+        termion::style::Invert,
+        termion::style::Reset,
+        after_chars.as_str()
+    )
+}
+
 fn main() {
     let mut lines = Vec::new();
-    if let Some(arg) = env::args().nth(1) {
-        match fs::read_to_string(&arg) {
+    let path = env::args().nth(1);
+    if let Some(arg) = &path {
+        match fs::read_to_string(arg) {
             Ok(data) => {
                 for line in data.lines() {
                     lines.push(line.to_string());
@@ -381,100 +509,307 @@ fn main() {
         height: height.into(),
         redraw: false,
         scroll: LayoutCursor { row: 0, col: 0 },
+        replaced: Vec::new(),
+        path,
+        quit: false,
     };
 
+    // Enable bracketed paste, so pasted text is not run as commands, and mouse reports
+    // while buttons are held
+    write!(stdout, "\x1B[?2004h\x1B[?1002h\x1B[?1006h").unwrap();
     editor.draw(&mut stdout, cursor, &parser).unwrap();
 
-    for termion_key_res in io::stdin().keys() {
-        let termion_key = match termion_key_res {
-            Ok(ok) => ok,
-            Err(err) => {
-                eprintln!("error reading keys: {}", err);
-                break;
+    // Read on another thread, so waiting for more bytes can time out
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buf = [0; 4096];
+        loop {
+            match stdin.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => {
+                    if sender.send(buf[..len].to_vec()).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    eprintln!("error reading keys: {}", err);
+                    break;
+                }
             }
+        }
+    });
+
+    let mut decoder = TerminalDecoder::new();
+    // Time, position, and number of clicks of the last mouse press
+    let mut last_click: Option<(Instant, usize, usize, u8)> = None;
+    'read: loop {
+        let received = if decoder.pending() {
+            receiver.recv_timeout(ESCAPE_TIME)
+        } else {
+            receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
         };
-        let key = match termion_key {
-            TermionKey::Backspace => Key::Backspace,
-            TermionKey::Left => Key::Left,
-            TermionKey::Right => Key::Right,
-            TermionKey::Up => Key::Up,
-            TermionKey::Down => Key::Down,
-            TermionKey::Home => Key::Home,
-            TermionKey::End => Key::End,
-            TermionKey::PageUp => Key::PageUp,
-            TermionKey::PageDown => Key::PageDown,
-            TermionKey::BackTab => Key::Backtab,
-            TermionKey::Delete => Key::Delete,
-            TermionKey::Insert => continue,
-            TermionKey::F(n) => continue,
-            TermionKey::Char(c) => Key::Char(c),
-            TermionKey::Alt(c) => continue,
-            TermionKey::Ctrl(c) => match c {
-                'c' => break,
-                _ => Key::Ctrl(c),
-            },
-            TermionKey::Null => continue,
-            TermionKey::Esc => Key::Escape,
-            _ => continue,
-        };
-        eprintln!("Key: {:?}", key);
-        parser.parse(key, false, |event| {
-            eprintln!("Event: {:?}", event);
-            match event {
-                Event::Delete => {
-                    match editor.delete_char(&mut cursor) {
-                        Ok(Some(_)) => {}
-                        Ok(None) => {
-                            // Join lines
-                            if let Some(next_line_i) = cursor.line.checked_add(1) {
-                                if next_line_i < editor.lines.len() {
-                                    let next_line = editor.lines.remove(next_line_i);
-                                    if let Some(line) = editor.lines.get_mut(cursor.line) {
-                                        cursor.index = line.len();
-                                        line.insert_str(cursor.index, &next_line);
+        let mut inputs = Vec::new();
+        match received {
+            Ok(bytes) => decoder.decode(&bytes, |input| inputs.push(input)),
+            // No more bytes arrived, so buffered bytes like a lone Escape are complete
+            Err(RecvTimeoutError::Timeout) => decoder.flush(|input| inputs.push(input)),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        for mut input in inputs {
+            if let TerminalInput::Key(Key {
+                code: KeyCode::Mouse(mouse),
+                ..
+            }) = &mut input
+            {
+                if let MouseAction::Press { clicks, .. } = &mut mouse.action {
+                    let now = Instant::now();
+                    *clicks = match last_click {
+                        Some((time, line, index, last_clicks))
+                            if line == mouse.line
+                                && index == mouse.index
+                                && now.duration_since(time) < MULTI_CLICK_TIME =>
+                        {
+                            last_clicks.saturating_add(1)
+                        }
+                        _ => 1,
+                    };
+                    last_click = Some((now, mouse.line, mouse.index, *clicks));
+                }
+                // Convert the screen cell to a position in the buffer
+                match editor.mouse_cursor(mouse.line, mouse.index) {
+                    Some(mouse_cursor) => {
+                        mouse.line = mouse_cursor.line;
+                        mouse.index = mouse_cursor.index;
+                    }
+                    None if !matches!(
+                        mouse.action,
+                        MouseAction::ScrollUp
+                            | MouseAction::ScrollDown
+                            | MouseAction::ScrollLeft
+                            | MouseAction::ScrollRight
+                    ) =>
+                    {
+                        continue;
+                    }
+                    None => {}
+                }
+            }
+            let on_event = |event| {
+                eprintln!("Event: {:?}", event);
+                match event {
+                    Event::Delete => {
+                        match editor.delete_char(&mut cursor) {
+                            Ok(Some(_)) => {}
+                            Ok(None) => {
+                                // Join lines
+                                if let Some(next_line_i) = cursor.line.checked_add(1) {
+                                    if next_line_i < editor.lines.len() {
+                                        let next_line = editor.lines.remove(next_line_i);
+                                        if let Some(line) = editor.lines.get_mut(cursor.line) {
+                                            cursor.index = line.len();
+                                            line.insert_str(cursor.index, &next_line);
+                                        }
                                     }
                                 }
                             }
+                            Err(err) => {
+                                eprintln!("failed to delete: {:?}", err);
+                            }
                         }
+                    }
+                    Event::DeleteInLine => match editor.delete_char(&mut cursor) {
+                        Ok(_) => {}
                         Err(err) => {
                             eprintln!("failed to delete: {:?}", err);
                         }
+                    },
+                    Event::Insert(c) => match editor.insert_char(&mut cursor, c) {
+                        Ok(()) => {}
+                        Err(err) => {
+                            eprintln!("failed to insert {:?}: {:?}", c, err);
+                        }
+                    },
+                    Event::InsertText(text) => {
+                        for c in text.chars() {
+                            if let Err(err) = editor.insert_char(&mut cursor, c) {
+                                eprintln!("failed to insert {:?}: {:?}", c, err);
+                                break;
+                            }
+                        }
                     }
-                }
-                Event::DeleteInLine => match editor.delete_char(&mut cursor) {
-                    Ok(_) => {}
-                    Err(err) => {
-                        eprintln!("failed to delete: {:?}", err);
+                    Event::Motion(motion) => {
+                        if let Some(new_cursor) = editor.motion(cursor, motion) {
+                            cursor = new_cursor;
+                        }
                     }
-                },
-                Event::Insert(c) => match editor.insert_char(&mut cursor, c) {
-                    Ok(()) => {}
-                    Err(err) => {
-                        eprintln!("failed to insert {:?}: {:?}", c, err);
+                    Event::NewLine => match editor.insert_char(&mut cursor, '\n') {
+                        Ok(()) => {
+                            // Removed by backspace in replace mode
+                            editor.replaced.push(None);
+                        }
+                        Err(err) => {
+                            eprintln!("failed to insert new line: {:?}", err);
+                        }
+                    },
+                    Event::ReplaceBackspace => {
+                        if let Some(new_cursor) = editor.motion(cursor, Motion::Left) {
+                            cursor = new_cursor;
+                        }
+                        let res = editor.delete_char(&mut cursor).and_then(|deleted| {
+                            match (deleted, editor.replaced.pop().flatten()) {
+                                // Restore replaced character
+                                (_, Some(c)) => {
+                                    editor.insert_char(&mut cursor, c)?;
+                                    if let Some(new_cursor) = editor.motion(cursor, Motion::Left) {
+                                        cursor = new_cursor;
+                                    }
+                                }
+                                // Remove line break
+                                (None, None) => {
+                                    if let Some(next_line_i) = cursor.line.checked_add(1) {
+                                        if next_line_i < editor.lines.len() {
+                                            let next_line = editor.lines.remove(next_line_i);
+                                            if let Some(line) = editor.lines.get_mut(cursor.line) {
+                                                line.push_str(&next_line);
+                                            }
+                                        }
+                                    }
+                                }
+                                // Appended character was deleted
+                                (Some(_), None) => {}
+                            }
+                            Ok(())
+                        });
+                        if let Err(err) = res {
+                            eprintln!("failed to restore replaced: {:?}", err);
+                        }
                     }
-                },
-                Event::Motion(motion) => {
-                    if let Some(new_cursor) = editor.motion(cursor, motion) {
-                        cursor = new_cursor;
+                    Event::VirtualReplaceChar(c)
+                        if editor
+                            .lines
+                            .get(cursor.line)
+                            .and_then(|line| line.get(cursor.index..))
+                            .is_some_and(|after| after.starts_with('\t'))
+                            && editor.tab_cells(cursor)
+                                > unicode_width::UnicodeWidthChar::width(c).unwrap_or(1) =>
+                    {
+                        // Insert before Tab, which becomes narrower
+                        match editor.insert_char(&mut cursor, c) {
+                            Ok(()) => editor.replaced.push(None),
+                            Err(err) => eprintln!("failed to insert {:?}: {:?}", c, err),
+                        }
+                    }
+                    Event::ReplaceChar(c) | Event::VirtualReplaceChar(c) => {
+                        let res = editor.delete_char(&mut cursor).and_then(|replaced| {
+                            editor.replaced.push(replaced);
+                            editor.insert_char(&mut cursor, c)
+                        });
+                        if let Err(err) = res {
+                            eprintln!("failed to replace {:?}: {:?}", c, err);
+                        }
+                    }
+                    Event::ReplaceCharInLine(c) => {
+                        let res = editor.delete_char(&mut cursor).and_then(|replaced| {
+                            // Nothing is replaced at end of line
+                            if replaced.is_some() {
+                                editor.insert_char(&mut cursor, c)?;
+                                if let Some(new_cursor) = editor.motion(cursor, Motion::LeftInLine)
+                                {
+                                    cursor = new_cursor;
+                                }
+                            }
+                            Ok(())
+                        });
+                        if let Err(err) = res {
+                            eprintln!("failed to replace {:?}: {:?}", c, err);
+                        }
+                    }
+                    Event::ScrollDown(lines) => {
+                        editor.scroll.row = editor.scroll.row.saturating_add(lines);
+                    }
+                    Event::ScrollUp(lines) => {
+                        editor.scroll.row = editor.scroll.row.saturating_sub(lines);
+                    }
+                    Event::ScrollCursor {
+                        position,
+                        reset_column,
+                    } => {
+                        if reset_column {
+                            if let Some(new_cursor) = editor.motion(cursor, Motion::SoftHome) {
+                                cursor = new_cursor;
+                            }
+                        }
+                        if let Some(layout_cursor) = editor.layout_cursor(cursor) {
+                            let rows = editor.height.saturating_sub(1);
+                            editor.scroll.row = match position {
+                                ScrollPosition::Top => layout_cursor.row,
+                                ScrollPosition::Center => {
+                                    layout_cursor.row.saturating_sub(rows / 2)
+                                }
+                                ScrollPosition::Bottom => {
+                                    layout_cursor.row.saturating_sub(rows.saturating_sub(1))
+                                }
+                            };
+                        }
+                    }
+                    Event::PutText(text) => {
+                        for c in text.chars() {
+                            if let Err(err) = editor.insert_char(&mut cursor, c) {
+                                eprintln!("failed to put {:?}: {:?}", c, err);
+                                break;
+                            }
+                        }
+                        // Leave the cursor on the last character
+                        if !text.is_empty() {
+                            if let Some(new_cursor) = editor.motion(cursor, Motion::Left) {
+                                cursor = new_cursor;
+                            }
+                        }
+                    }
+                    Event::Quit { force: _ } => {
+                        //TODO: refuse to quit with changes unless forced
+                        editor.quit = true;
+                    }
+                    Event::Redraw => {
+                        editor.redraw = true;
+                    }
+                    Event::Write(request) => {
+                        if let Err(err) = editor.write(cursor, &request) {
+                            eprintln!("failed to write: {}", err);
+                        }
+                    }
+                    Event::WriteQuit(request) => match editor.write(cursor, &request) {
+                        Ok(()) => editor.quit = true,
+                        Err(err) => eprintln!("failed to write: {}", err),
+                    },
+                    _ => {
+                        eprintln!("TODO {:?}", event);
                     }
                 }
-                Event::NewLine => match editor.insert_char(&mut cursor, '\n') {
-                    Ok(()) => {}
-                    Err(err) => {
-                        eprintln!("failed to insert new line: {:?}", err);
+            };
+            match input {
+                TerminalInput::Key(key) => {
+                    if key == Key::new(KeyCode::Char('c'), Modifiers::CTRL) {
+                        break 'read;
                     }
-                },
-                Event::Redraw => {
-                    editor.redraw = true;
+                    eprintln!("Key: {}", key);
+                    parser.parse(key, false, on_event);
                 }
-                _ => {
-                    eprintln!("TODO {:?}", event);
+                TerminalInput::Paste(text) => {
+                    eprintln!("Paste: {:?}", text);
+                    parser.paste(&text, false, on_event);
                 }
             }
-        });
-        if editor.redraw {
-            editor.draw(&mut stdout, cursor, &parser).unwrap();
-            editor.redraw = false;
+            if editor.quit {
+                break 'read;
+            }
+            if editor.redraw {
+                editor.draw(&mut stdout, cursor, &parser).unwrap();
+                editor.redraw = false;
+            }
         }
     }
+
+    write!(stdout, "\x1B[?1006l\x1B[?1002l\x1B[?2004l").unwrap();
 }
@@ -0,0 +1,108 @@
+use alloc::{string::String, vec::Vec};
+
+/// Command line completion state, which can be used to render a wildmenu
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Completion {
+    /// Byte index in the command line where candidates are inserted
+    pub start: usize,
+    /// Text that was replaced by candidates, restored when cycling past the last candidate
+    pub original: String,
+    /// Candidates provided by the host
+    pub candidates: Vec<String>,
+    /// Index of the selected candidate, None if original text is shown
+    pub selected: Option<usize>,
+}
+
+impl Completion {
+    /// Create completion state replacing the text from start to cursor with candidates.
+    /// Returns None if start is not a valid index into value.
+    pub fn new(value: &str, start: usize, cursor: usize, candidates: Vec<String>) -> Option<Self> {
+        let original = value.get(start..cursor)?.into();
+        Some(Self {
+            start,
+            original,
+            candidates,
+            selected: None,
+        })
+    }
+
+    /// Select the next candidate, wrapping around to the original text
+    pub fn next(&mut self, value: &mut String, cursor: &mut usize) {
+        let selected = match self.selected {
+            Some(index) => index
+                .checked_add(1)
+                .filter(|&index| index < self.candidates.len()),
+            None if self.candidates.is_empty() => None,
+            None => Some(0),
+        };
+        self.select(value, cursor, selected);
+    }
+
+    /// Select the previous candidate, wrapping around to the original text
+    pub fn previous(&mut self, value: &mut String, cursor: &mut usize) {
+        let selected = match self.selected {
+            Some(index) => index.checked_sub(1),
+            None => self.candidates.len().checked_sub(1),
+        };
+        self.select(value, cursor, selected);
+    }
+
+    /// Select a candidate and update the command line to show it
+    pub fn select(&mut self, value: &mut String, cursor: &mut usize, selected: Option<usize>) {
+        let text = match selected.and_then(|index| self.candidates.get(index)) {
+            Some(candidate) => candidate,
+            None => &self.original,
+        };
+        if value.get(self.start..*cursor).is_none() {
+            return;
+        }
+        value.replace_range(self.start..*cursor, text);
+        *cursor = self.start.saturating_add(text.len());
+        self.selected = selected;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use alloc::{string::ToString, vec};
+
+    use super::*;
+
+    #[test]
+    fn cycle() {
+        let mut value = "e f".to_string();
+        let mut cursor = value.len();
+        let candidates = vec!["foo".to_string(), "far".to_string()];
+        let mut completion = Completion::new(&value, 2, cursor, candidates).unwrap();
+        let mut next = |completion: &mut Completion, forwards| {
+            if forwards {
+                completion.next(&mut value, &mut cursor);
+            } else {
+                completion.previous(&mut value, &mut cursor);
+            }
+            (value.clone(), cursor, completion.selected)
+        };
+        assert_eq!(next(&mut completion, true), ("e foo".into(), 5, Some(0)));
+        assert_eq!(next(&mut completion, true), ("e far".into(), 5, Some(1)));
+        // Past the last candidate the original text is shown, then the first candidate
+        assert_eq!(next(&mut completion, true), ("e f".into(), 3, None));
+        assert_eq!(next(&mut completion, true), ("e foo".into(), 5, Some(0)));
+        assert_eq!(next(&mut completion, false), ("e f".into(), 3, None));
+        assert_eq!(next(&mut completion, false), ("e far".into(), 5, Some(1)));
+    }
+
+    #[test]
+    fn no_candidates() {
+        let mut value = "e f".to_string();
+        let mut cursor = value.len();
+        let mut completion = Completion::new(&value, 2, cursor, vec![]).unwrap();
+        completion.next(&mut value, &mut cursor);
+        completion.previous(&mut value, &mut cursor);
+        assert_eq!(
+            (value.as_str(), cursor, completion.selected),
+            ("e f", 3, None)
+        );
+        assert_eq!(Completion::new("e f", 4, 3, vec![]), None);
+    }
+}
@@ -0,0 +1,359 @@
+use core::mem;
+
+use crate::{
+    Event, Key, KeyCode, Modifiers, Motion, Mouse, MouseAction, MouseButton, Parser, TextObject,
+    Word, WriteRequest,
+};
+
+/// Register used for cut, copy, and paste
+pub const CUA_CLIPBOARD_REGISTER: char = '+';
+
+/// Lines scrolled by the mouse wheel
+pub const CUA_MOUSE_SCROLL_LINES: usize = 3;
+
+/// Parser for conventional non-modal editing, with Ctrl-C, Ctrl-V, Ctrl-X, and Ctrl-Z,
+/// and Shift with movement keys to select
+#[derive(Debug)]
+pub struct CuaParser {
+    /// True while characters are being typed, so they are undone together
+    inserting: bool,
+    /// True if reset interrupted inserting, so the change is finished on the next key
+    finish_change: bool,
+}
+
+impl CuaParser {
+    pub fn new() -> Self {
+        Self {
+            inserting: false,
+            finish_change: false,
+        }
+    }
+
+    /// Move, clearing the selection if extend is false or starting it if extend is true
+    fn motion<F: FnMut(Event)>(motion: Motion, extend: bool, selection: bool, callback: &mut F) {
+        if extend {
+            if !selection {
+                callback(Event::SelectStart);
+            }
+        } else if selection {
+            callback(Event::SelectClear);
+        }
+        callback(Event::Motion(motion));
+    }
+
+    /// Delete the selection, used before typed text replaces it
+    fn delete_selection<F: FnMut(Event)>(callback: &mut F) {
+        callback(Event::Delete);
+        callback(Event::SelectClear);
+    }
+
+    /// Delete the selection, or the text moved over by motion if there is no selection
+    fn delete<F: FnMut(Event)>(motion: Motion, selection: bool, callback: &mut F) {
+        callback(Event::ChangeStart);
+        if !selection {
+            callback(Event::SelectStart);
+            callback(Event::Motion(motion));
+        }
+        CuaParser::delete_selection(callback);
+        callback(Event::ChangeFinish);
+    }
+
+    /// Click to move, Shift-click or drag to select, and double or triple click to select
+    /// a word or line
+    fn mouse<F: FnMut(Event)>(mouse: Mouse, extend: bool, selection: bool, callback: &mut F) {
+        let position = Motion::Position {
+            line: mouse.line,
+            index: mouse.index,
+        };
+        match mouse.action {
+            MouseAction::Press {
+                button: MouseButton::Left,
+                clicks: 0 | 1,
+            } => CuaParser::motion(position, extend, selection, callback),
+            MouseAction::Press {
+                button: MouseButton::Left,
+                clicks,
+            } => {
+                if selection {
+                    callback(Event::SelectClear);
+                }
+                callback(Event::Motion(position));
+                if clicks == 2 {
+                    callback(Event::SelectStart);
+                    callback(Event::SelectTextObject(
+                        TextObject::Word(Word::Lower),
+                        false,
+                    ));
+                } else {
+                    callback(Event::SelectLineStart);
+                }
+            }
+            MouseAction::Drag(MouseButton::Left) => {
+                CuaParser::motion(position, true, selection, callback);
+            }
+            MouseAction::ScrollUp => callback(Event::ScrollUp(CUA_MOUSE_SCROLL_LINES)),
+            MouseAction::ScrollDown => callback(Event::ScrollDown(CUA_MOUSE_SCROLL_LINES)),
+            MouseAction::ScrollLeft => callback(Event::ScrollLeft(CUA_MOUSE_SCROLL_LINES)),
+            MouseAction::ScrollRight => callback(Event::ScrollRight(CUA_MOUSE_SCROLL_LINES)),
+            //TODO: context menu on right click
+            _ => {}
+        }
+    }
+
+    /// Handle keys other than movement keys
+    fn command<F: FnMut(Event)>(&mut self, key: Key, selection: bool, callback: &mut F) {
+        match (key.modifiers, key.code) {
+            // Select all, with the cursor at the end
+            (Modifiers::CTRL, KeyCode::Char('a')) => {
+                if selection {
+                    callback(Event::SelectClear);
+                }
+                callback(Event::Motion(Motion::GotoLine(1)));
+                callback(Event::SelectStart);
+                callback(Event::Motion(Motion::GotoEof));
+            }
+
+            // Typing replaces the selection
+            (Modifiers::NONE, KeyCode::Char(c)) => {
+                if !self.inserting {
+                    callback(Event::ChangeStart);
+                    self.inserting = true;
+                }
+                if selection {
+                    CuaParser::delete_selection(callback);
+                }
+                callback(Event::Insert(c));
+            }
+            (Modifiers::NONE, KeyCode::Enter) => {
+                callback(Event::ChangeStart);
+                if selection {
+                    CuaParser::delete_selection(callback);
+                }
+                callback(Event::NewLine);
+                callback(Event::ChangeFinish);
+            }
+            // Tab indents the selected lines, or inserts a tab
+            (Modifiers::NONE, KeyCode::Tab) => {
+                callback(Event::ChangeStart);
+                if selection {
+                    callback(Event::ShiftRight);
+                } else {
+                    callback(Event::Insert('\t'));
+                }
+                callback(Event::ChangeFinish);
+            }
+            (Modifiers::SHIFT, KeyCode::Tab) => {
+                callback(Event::ChangeStart);
+                if !selection {
+                    callback(Event::SelectStart);
+                }
+                callback(Event::ShiftLeft);
+                if !selection {
+                    callback(Event::SelectClear);
+                }
+                callback(Event::ChangeFinish);
+            }
+
+            // Deleting
+            (Modifiers::NONE, KeyCode::Backspace) => {
+                callback(Event::ChangeStart);
+                if selection {
+                    CuaParser::delete_selection(callback);
+                } else {
+                    callback(Event::Backspace);
+                }
+                callback(Event::ChangeFinish);
+            }
+            (Modifiers::NONE, KeyCode::Delete) => {
+                callback(Event::ChangeStart);
+                if selection {
+                    CuaParser::delete_selection(callback);
+                } else {
+                    callback(Event::Delete);
+                }
+                callback(Event::ChangeFinish);
+            }
+            (Modifiers::CTRL, KeyCode::Backspace) => {
+                CuaParser::delete(Motion::PreviousWordStart(Word::Lower), selection, callback);
+            }
+            (Modifiers::CTRL, KeyCode::Delete) => {
+                CuaParser::delete(Motion::NextWordStart(Word::Lower), selection, callback);
+            }
+
+            // Clipboard
+            (Modifiers::CTRL, KeyCode::Char('c')) => {
+                if selection {
+                    callback(Event::Yank {
+                        register: CUA_CLIPBOARD_REGISTER,
+                    });
+                }
+            }
+            (Modifiers::CTRL, KeyCode::Char('x')) => {
+                if selection {
+                    callback(Event::ChangeStart);
+                    callback(Event::Yank {
+                        register: CUA_CLIPBOARD_REGISTER,
+                    });
+                    CuaParser::delete_selection(callback);
+                    callback(Event::ChangeFinish);
+                }
+            }
+            (Modifiers::CTRL, KeyCode::Char('v')) => {
+                callback(Event::ChangeStart);
+                if selection {
+                    CuaParser::delete_selection(callback);
+                }
+                callback(Event::PutAndMove {
+                    register: CUA_CLIPBOARD_REGISTER,
+                    after: false,
+                });
+                callback(Event::ChangeFinish);
+            }
+
+            // Undo and redo
+            (Modifiers::CTRL, KeyCode::Char('z')) => callback(Event::Undo),
+            (Modifiers::CTRL, KeyCode::Char('y')) => callback(Event::Redo),
+
+            // Files
+            (Modifiers::CTRL, KeyCode::Char('s')) => callback(Event::Write(WriteRequest {
+                range: None,
+                path: None,
+                force: false,
+                modified_only: false,
+                save_as: false,
+            })),
+            (Modifiers::CTRL, KeyCode::Char('q')) => callback(Event::Quit { force: false }),
+
+            (modifiers, KeyCode::Mouse(mouse)) => CuaParser::mouse(
+                mouse,
+                modifiers.contains(Modifiers::SHIFT),
+                selection,
+                callback,
+            ),
+
+            (Modifiers::NONE, KeyCode::Escape) => {
+                if selection {
+                    callback(Event::SelectClear);
+                }
+                callback(Event::Escape);
+            }
+            //TODO: find, replace, and overwrite mode
+            _ => {
+                log::info!("TODO: cua key {:?}", key);
+            }
+        }
+    }
+}
+
+/// Motion for a movement key, without Shift
+fn cua_motion(modifiers: Modifiers, code: KeyCode) -> Option<Motion> {
+    Some(match (modifiers, code) {
+        (Modifiers::NONE, KeyCode::Left) => Motion::Left,
+        (Modifiers::NONE, KeyCode::Right) => Motion::Right,
+        (Modifiers::NONE, KeyCode::Up) => Motion::Up,
+        (Modifiers::NONE, KeyCode::Down) => Motion::Down,
+        (Modifiers::NONE, KeyCode::Home) => Motion::Home,
+        (Modifiers::NONE, KeyCode::End) => Motion::End,
+        (Modifiers::NONE, KeyCode::PageUp) => Motion::PageUp,
+        (Modifiers::NONE, KeyCode::PageDown) => Motion::PageDown,
+        (Modifiers::CTRL, KeyCode::Left) => Motion::PreviousWordStart(Word::Lower),
+        (Modifiers::CTRL, KeyCode::Right) => Motion::NextWordStart(Word::Lower),
+        (Modifiers::CTRL, KeyCode::Home) => Motion::GotoLine(1),
+        (Modifiers::CTRL, KeyCode::End) => Motion::GotoEof,
+        _ => return None,
+    })
+}
+
+impl Parser for CuaParser {
+    fn reset(&mut self) {
+        // The callback is not available here, so the change is finished on the next key
+        self.finish_change |= mem::take(&mut self.inserting);
+    }
+
+    fn parse<F: FnMut(Event)>(&mut self, key: Key, selection: bool, mut callback: F) {
+        // Normalize key, so we don't deal with control characters below
+        let key = key.normalize();
+        let callback = &mut callback;
+
+        if mem::take(&mut self.finish_change) {
+            callback(Event::ChangeFinish);
+        }
+        // Characters typed together are undone together
+        if self.inserting
+            && !matches!(
+                (key.modifiers, key.code),
+                (Modifiers::NONE, KeyCode::Char(_))
+            )
+        {
+            callback(Event::ChangeFinish);
+            self.inserting = false;
+        }
+
+        // Shift with a movement key extends the selection
+        let extend = key.modifiers.contains(Modifiers::SHIFT);
+        let modifiers = key.modifiers.difference(Modifiers::SHIFT);
+        match cua_motion(modifiers, key.code) {
+            Some(motion) => CuaParser::motion(motion, extend, selection, callback),
+            None => self.command(key, selection, callback),
+        }
+
+        //TODO: optimize redraw
+        callback(Event::Redraw);
+    }
+
+    fn paste<F: FnMut(Event)>(&mut self, text: &str, selection: bool, mut callback: F) {
+        // Pasted text replaces the selection like Ctrl-V, and is undone separately from typing
+        if mem::take(&mut self.inserting) | mem::take(&mut self.finish_change) {
+            callback(Event::ChangeFinish);
+        }
+        callback(Event::ChangeStart);
+        if selection {
+            CuaParser::delete_selection(&mut callback);
+        }
+        callback(Event::InsertText(text.into()));
+        callback(Event::ChangeFinish);
+
+        //TODO: optimize redraw
+        callback(Event::Redraw);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn reset_finishes_insert() {
+        let mut parser = CuaParser::new();
+        let mut events = Vec::new();
+        parser.parse_keys("ab", false, |event| events.push(event));
+        parser.reset();
+        parser.parse_keys("cd<Left>", false, |event| events.push(event));
+        parser.reset();
+        parser.paste("ef", false, |event| events.push(event));
+
+        // Every change is finished before the next one starts
+        let mut open = false;
+        for event in events.iter() {
+            match event {
+                Event::ChangeStart => {
+                    assert!(!open);
+                    open = true;
+                }
+                Event::ChangeFinish => {
+                    assert!(open);
+                    open = false;
+                }
+                _ => {}
+            }
+        }
+        assert!(!open);
+        let starts = events
+            .iter()
+            .filter(|event| matches!(event, Event::ChangeStart))
+            .count();
+        assert_eq!(starts, 3);
+    }
+}
@@ -0,0 +1,574 @@
+use alloc::vec::Vec;
+
+/// Built in digraphs from RFC 1345, as used by vim
+pub static DIGRAPHS_DEFAULT: &[(char, char, char)] = &[
+    ('N', 'S', '\u{00A0}'),
+    ('!', 'I', '\u{00A1}'),
+    ('C', 't', '\u{00A2}'),
+    ('P', 'd', '\u{00A3}'),
+    ('C', 'u', '\u{00A4}'),
+    ('Y', 'e', '\u{00A5}'),
+    ('B', 'B', '\u{00A6}'),
+    ('S', 'E', '\u{00A7}'),
+    ('\'', ':', '\u{00A8}'),
+    ('C', 'o', '\u{00A9}'),
+    ('-', 'a', '\u{00AA}'),
+    ('<', '<', '\u{00AB}'),
+    ('N', 'O', '\u{00AC}'),
+    ('-', '-', '\u{00AD}'),
+    ('R', 'g', '\u{00AE}'),
+    ('\'', 'm', '\u{00AF}'),
+    ('D', 'G', '\u{00B0}'),
+    ('+', '-', '\u{00B1}'),
+    ('2', 'S', '\u{00B2}'),
+    ('3', 'S', '\u{00B3}'),
+    ('\'', '\'', '\u{00B4}'),
+    ('M', 'y', '\u{00B5}'),
+    ('P', 'I', '\u{00B6}'),
+    ('.', 'M', '\u{00B7}'),
+    ('\'', ',', '\u{00B8}'),
+    ('1', 'S', '\u{00B9}'),
+    ('-', 'o', '\u{00BA}'),
+    ('>', '>', '\u{00BB}'),
+    ('1', '4', '\u{00BC}'),
+    ('1', '2', '\u{00BD}'),
+    ('3', '4', '\u{00BE}'),
+    ('?', 'I', '\u{00BF}'),
+    ('A', '!', '\u{00C0}'),
+    ('A', '\'', '\u{00C1}'),
+    ('A', '>', '\u{00C2}'),
+    ('A', '?', '\u{00C3}'),
+    ('A', ':', '\u{00C4}'),
+    ('A', 'A', '\u{00C5}'),
+    ('A', 'E', '\u{00C6}'),
+    ('C', ',', '\u{00C7}'),
+    ('E', '!', '\u{00C8}'),
+    ('E', '\'', '\u{00C9}'),
+    ('E', '>', '\u{00CA}'),
+    ('E', ':', '\u{00CB}'),
+    ('I', '!', '\u{00CC}'),
+    ('I', '\'', '\u{00CD}'),
+    ('I', '>', '\u{00CE}'),
+    ('I', ':', '\u{00CF}'),
+    ('D', '-', '\u{00D0}'),
+    ('N', '?', '\u{00D1}'),
+    ('O', '!', '\u{00D2}'),
+    ('O', '\'', '\u{00D3}'),
+    ('O', '>', '\u{00D4}'),
+    ('O', '?', '\u{00D5}'),
+    ('O', ':', '\u{00D6}'),
+    ('*', 'X', '\u{00D7}'),
+    ('O', '/', '\u{00D8}'),
+    ('U', '!', '\u{00D9}'),
+    ('U', '\'', '\u{00DA}'),
+    ('U', '>', '\u{00DB}'),
+    ('U', ':', '\u{00DC}'),
+    ('Y', '\'', '\u{00DD}'),
+    ('T', 'H', '\u{00DE}'),
+    ('s', 's', '\u{00DF}'),
+    ('a', '!', '\u{00E0}'),
+    ('a', '\'', '\u{00E1}'),
+    ('a', '>', '\u{00E2}'),
+    ('a', '?', '\u{00E3}'),
+    ('a', ':', '\u{00E4}'),
+    ('a', 'a', '\u{00E5}'),
+    ('a', 'e', '\u{00E6}'),
+    ('c', ',', '\u{00E7}'),
+    ('e', '!', '\u{00E8}'),
+    ('e', '\'', '\u{00E9}'),
+    ('e', '>', '\u{00EA}'),
+    ('e', ':', '\u{00EB}'),
+    ('i', '!', '\u{00EC}'),
+    ('i', '\'', '\u{00ED}'),
+    ('i', '>', '\u{00EE}'),
+    ('i', ':', '\u{00EF}'),
+    ('d', '-', '\u{00F0}'),
+    ('n', '?', '\u{00F1}'),
+    ('o', '!', '\u{00F2}'),
+    ('o', '\'', '\u{00F3}'),
+    ('o', '>', '\u{00F4}'),
+    ('o', '?', '\u{00F5}'),
+    ('o', ':', '\u{00F6}'),
+    ('-', ':', '\u{00F7}'),
+    ('o', '/', '\u{00F8}'),
+    ('u', '!', '\u{00F9}'),
+    ('u', '\'', '\u{00FA}'),
+    ('u', '>', '\u{00FB}'),
+    ('u', ':', '\u{00FC}'),
+    ('y', '\'', '\u{00FD}'),
+    ('t', 'h', '\u{00FE}'),
+    ('y', ':', '\u{00FF}'),
+    ('A', '-', '\u{0100}'),
+    ('a', '-', '\u{0101}'),
+    ('A', '(', '\u{0102}'),
+    ('a', '(', '\u{0103}'),
+    ('A', ';', '\u{0104}'),
+    ('a', ';', '\u{0105}'),
+    ('C', '\'', '\u{0106}'),
+    ('c', '\'', '\u{0107}'),
+    ('C', '>', '\u{0108}'),
+    ('c', '>', '\u{0109}'),
+    ('C', '.', '\u{010A}'),
+    ('c', '.', '\u{010B}'),
+    ('C', '<', '\u{010C}'),
+    ('c', '<', '\u{010D}'),
+    ('D', '<', '\u{010E}'),
+    ('d', '<', '\u{010F}'),
+    ('D', '/', '\u{0110}'),
+    ('d', '/', '\u{0111}'),
+    ('E', '-', '\u{0112}'),
+    ('e', '-', '\u{0113}'),
+    ('E', '(', '\u{0114}'),
+    ('e', '(', '\u{0115}'),
+    ('E', '.', '\u{0116}'),
+    ('e', '.', '\u{0117}'),
+    ('E', ';', '\u{0118}'),
+    ('e', ';', '\u{0119}'),
+    ('E', '<', '\u{011A}'),
+    ('e', '<', '\u{011B}'),
+    ('G', '>', '\u{011C}'),
+    ('g', '>', '\u{011D}'),
+    ('G', '(', '\u{011E}'),
+    ('g', '(', '\u{011F}'),
+    ('G', '.', '\u{0120}'),
+    ('g', '.', '\u{0121}'),
+    ('G', ',', '\u{0122}'),
+    ('g', ',', '\u{0123}'),
+    ('H', '>', '\u{0124}'),
+    ('h', '>', '\u{0125}'),
+    ('H', '/', '\u{0126}'),
+    ('h', '/', '\u{0127}'),
+    ('I', '?', '\u{0128}'),
+    ('i', '?', '\u{0129}'),
+    ('I', '-', '\u{012A}'),
+    ('i', '-', '\u{012B}'),
+    ('I', '(', '\u{012C}'),
+    ('i', '(', '\u{012D}'),
+    ('I', ';', '\u{012E}'),
+    ('i', ';', '\u{012F}'),
+    ('I', '.', '\u{0130}'),
+    ('i', '.', '\u{0131}'),
+    ('I', 'J', '\u{0132}'),
+    ('i', 'j', '\u{0133}'),
+    ('J', '>', '\u{0134}'),
+    ('j', '>', '\u{0135}'),
+    ('K', ',', '\u{0136}'),
+    ('k', ',', '\u{0137}'),
+    ('k', 'k', '\u{0138}'),
+    ('L', '\'', '\u{0139}'),
+    ('l', '\'', '\u{013A}'),
+    ('L', ',', '\u{013B}'),
+    ('l', ',', '\u{013C}'),
+    ('L', '<', '\u{013D}'),
+    ('l', '<', '\u{013E}'),
+    ('L', '.', '\u{013F}'),
+    ('l', '.', '\u{0140}'),
+    ('L', '/', '\u{0141}'),
+    ('l', '/', '\u{0142}'),
+    ('N', '\'', '\u{0143}'),
+    ('n', '\'', '\u{0144}'),
+    ('N', ',', '\u{0145}'),
+    ('n', ',', '\u{0146}'),
+    ('N', '<', '\u{0147}'),
+    ('n', '<', '\u{0148}'),
+    ('\'', 'n', '\u{0149}'),
+    ('N', 'G', '\u{014A}'),
+    ('n', 'g', '\u{014B}'),
+    ('O', '-', '\u{014C}'),
+    ('o', '-', '\u{014D}'),
+    ('O', '(', '\u{014E}'),
+    ('o', '(', '\u{014F}'),
+    ('O', '"', '\u{0150}'),
+    ('o', '"', '\u{0151}'),
+    ('O', 'E', '\u{0152}'),
+    ('o', 'e', '\u{0153}'),
+    ('R', '\'', '\u{0154}'),
+    ('r', '\'', '\u{0155}'),
+    ('R', ',', '\u{0156}'),
+    ('r', ',', '\u{0157}'),
+    ('R', '<', '\u{0158}'),
+    ('r', '<', '\u{0159}'),
+    ('S', '\'', '\u{015A}'),
+    ('s', '\'', '\u{015B}'),
+    ('S', '>', '\u{015C}'),
+    ('s', '>', '\u{015D}'),
+    ('S', ',', '\u{015E}'),
+    ('s', ',', '\u{015F}'),
+    ('S', '<', '\u{0160}'),
+    ('s', '<', '\u{0161}'),
+    ('T', ',', '\u{0162}'),
+    ('t', ',', '\u{0163}'),
+    ('T', '<', '\u{0164}'),
+    ('t', '<', '\u{0165}'),
+    ('T', '/', '\u{0166}'),
+    ('t', '/', '\u{0167}'),
+    ('U', '?', '\u{0168}'),
+    ('u', '?', '\u{0169}'),
+    ('U', '-', '\u{016A}'),
+    ('u', '-', '\u{016B}'),
+    ('U', '(', '\u{016C}'),
+    ('u', '(', '\u{016D}'),
+    ('U', '0', '\u{016E}'),
+    ('u', '0', '\u{016F}'),
+    ('U', '"', '\u{0170}'),
+    ('u', '"', '\u{0171}'),
+    ('U', ';', '\u{0172}'),
+    ('u', ';', '\u{0173}'),
+    ('W', '>', '\u{0174}'),
+    ('w', '>', '\u{0175}'),
+    ('Y', '>', '\u{0176}'),
+    ('y', '>', '\u{0177}'),
+    ('Y', ':', '\u{0178}'),
+    ('Z', '\'', '\u{0179}'),
+    ('z', '\'', '\u{017A}'),
+    ('Z', '.', '\u{017B}'),
+    ('z', '.', '\u{017C}'),
+    ('Z', '<', '\u{017D}'),
+    ('z', '<', '\u{017E}'),
+    ('A', '*', '\u{0391}'),
+    ('B', '*', '\u{0392}'),
+    ('G', '*', '\u{0393}'),
+    ('D', '*', '\u{0394}'),
+    ('E', '*', '\u{0395}'),
+    ('Z', '*', '\u{0396}'),
+    ('Y', '*', '\u{0397}'),
+    ('H', '*', '\u{0398}'),
+    ('I', '*', '\u{0399}'),
+    ('K', '*', '\u{039A}'),
+    ('L', '*', '\u{039B}'),
+    ('M', '*', '\u{039C}'),
+    ('N', '*', '\u{039D}'),
+    ('C', '*', '\u{039E}'),
+    ('O', '*', '\u{039F}'),
+    ('P', '*', '\u{03A0}'),
+    ('R', '*', '\u{03A1}'),
+    ('S', '*', '\u{03A3}'),
+    ('T', '*', '\u{03A4}'),
+    ('U', '*', '\u{03A5}'),
+    ('F', '*', '\u{03A6}'),
+    ('X', '*', '\u{03A7}'),
+    ('Q', '*', '\u{03A8}'),
+    ('W', '*', '\u{03A9}'),
+    ('a', '*', '\u{03B1}'),
+    ('b', '*', '\u{03B2}'),
+    ('g', '*', '\u{03B3}'),
+    ('d', '*', '\u{03B4}'),
+    ('e', '*', '\u{03B5}'),
+    ('z', '*', '\u{03B6}'),
+    ('y', '*', '\u{03B7}'),
+    ('h', '*', '\u{03B8}'),
+    ('i', '*', '\u{03B9}'),
+    ('k', '*', '\u{03BA}'),
+    ('l', '*', '\u{03BB}'),
+    ('m', '*', '\u{03BC}'),
+    ('n', '*', '\u{03BD}'),
+    ('c', '*', '\u{03BE}'),
+    ('o', '*', '\u{03BF}'),
+    ('p', '*', '\u{03C0}'),
+    ('r', '*', '\u{03C1}'),
+    ('*', 's', '\u{03C2}'),
+    ('s', '*', '\u{03C3}'),
+    ('t', '*', '\u{03C4}'),
+    ('u', '*', '\u{03C5}'),
+    ('f', '*', '\u{03C6}'),
+    ('x', '*', '\u{03C7}'),
+    ('q', '*', '\u{03C8}'),
+    ('w', '*', '\u{03C9}'),
+    ('A', '=', '\u{0410}'),
+    ('B', '=', '\u{0411}'),
+    ('V', '=', '\u{0412}'),
+    ('G', '=', '\u{0413}'),
+    ('D', '=', '\u{0414}'),
+    ('E', '=', '\u{0415}'),
+    ('Z', '%', '\u{0416}'),
+    ('Z', '=', '\u{0417}'),
+    ('I', '=', '\u{0418}'),
+    ('J', '=', '\u{0419}'),
+    ('K', '=', '\u{041A}'),
+    ('L', '=', '\u{041B}'),
+    ('M', '=', '\u{041C}'),
+    ('N', '=', '\u{041D}'),
+    ('O', '=', '\u{041E}'),
+    ('P', '=', '\u{041F}'),
+    ('R', '=', '\u{0420}'),
+    ('S', '=', '\u{0421}'),
+    ('T', '=', '\u{0422}'),
+    ('U', '=', '\u{0423}'),
+    ('F', '=', '\u{0424}'),
+    ('H', '=', '\u{0425}'),
+    ('C', '=', '\u{0426}'),
+    ('C', '%', '\u{0427}'),
+    ('S', '%', '\u{0428}'),
+    ('S', 'c', '\u{0429}'),
+    ('=', '"', '\u{042A}'),
+    ('Y', '=', '\u{042B}'),
+    ('%', '"', '\u{042C}'),
+    ('J', 'E', '\u{042D}'),
+    ('J', 'U', '\u{042E}'),
+    ('J', 'A', '\u{042F}'),
+    ('a', '=', '\u{0430}'),
+    ('b', '=', '\u{0431}'),
+    ('v', '=', '\u{0432}'),
+    ('g', '=', '\u{0433}'),
+    ('d', '=', '\u{0434}'),
+    ('e', '=', '\u{0435}'),
+    ('z', '%', '\u{0436}'),
+    ('z', '=', '\u{0437}'),
+    ('i', '=', '\u{0438}'),
+    ('j', '=', '\u{0439}'),
+    ('k', '=', '\u{043A}'),
+    ('l', '=', '\u{043B}'),
+    ('m', '=', '\u{043C}'),
+    ('n', '=', '\u{043D}'),
+    ('o', '=', '\u{043E}'),
+    ('p', '=', '\u{043F}'),
+    ('r', '=', '\u{0440}'),
+    ('s', '=', '\u{0441}'),
+    ('t', '=', '\u{0442}'),
+    ('u', '=', '\u{0443}'),
+    ('f', '=', '\u{0444}'),
+    ('h', '=', '\u{0445}'),
+    ('c', '=', '\u{0446}'),
+    ('c', '%', '\u{0447}'),
+    ('s', '%', '\u{0448}'),
+    ('s', 'c', '\u{0449}'),
+    ('=', '\'', '\u{044A}'),
+    ('y', '=', '\u{044B}'),
+    ('%', '\'', '\u{044C}'),
+    ('j', 'e', '\u{044D}'),
+    ('j', 'u', '\u{044E}'),
+    ('j', 'a', '\u{044F}'),
+    ('I', 'O', '\u{0401}'),
+    ('i', 'o', '\u{0451}'),
+    ('1', 'N', '\u{2002}'),
+    ('1', 'M', '\u{2003}'),
+    ('-', '1', '\u{2010}'),
+    ('-', 'N', '\u{2013}'),
+    ('-', 'M', '\u{2014}'),
+    ('-', '3', '\u{2015}'),
+    ('!', '2', '\u{2016}'),
+    ('=', '2', '\u{2017}'),
+    ('\'', '6', '\u{2018}'),
+    ('\'', '9', '\u{2019}'),
+    ('.', '9', '\u{201A}'),
+    ('9', '\'', '\u{201B}'),
+    ('"', '6', '\u{201C}'),
+    ('"', '9', '\u{201D}'),
+    (':', '9', '\u{201E}'),
+    ('9', '"', '\u{201F}'),
+    ('/', '-', '\u{2020}'),
+    ('/', '=', '\u{2021}'),
+    ('o', 'o', '\u{2022}'),
+    ('.', '.', '\u{2025}'),
+    (',', '.', '\u{2026}'),
+    ('%', '0', '\u{2030}'),
+    ('1', '\'', '\u{2032}'),
+    ('2', '\'', '\u{2033}'),
+    ('3', '\'', '\u{2034}'),
+    ('<', '1', '\u{2039}'),
+    ('>', '1', '\u{203A}'),
+    ('E', 'u', '\u{20AC}'),
+    ('=', 'R', '\u{20BD}'),
+    ('=', 'P', '\u{20BD}'),
+    ('L', 'i', '\u{20A4}'),
+    ('P', 't', '\u{20A7}'),
+    ('W', '=', '\u{20A9}'),
+    ('o', 'C', '\u{2103}'),
+    ('c', 'o', '\u{2105}'),
+    ('o', 'F', '\u{2109}'),
+    ('N', '0', '\u{2116}'),
+    ('P', 'O', '\u{2117}'),
+    ('R', 'x', '\u{211E}'),
+    ('S', 'M', '\u{2120}'),
+    ('T', 'M', '\u{2122}'),
+    ('O', 'm', '\u{2126}'),
+    ('A', 'O', '\u{212B}'),
+    ('<', '-', '\u{2190}'),
+    ('-', '!', '\u{2191}'),
+    ('-', '>', '\u{2192}'),
+    ('-', 'v', '\u{2193}'),
+    ('<', '>', '\u{2194}'),
+    ('U', 'D', '\u{2195}'),
+    ('<', '=', '\u{21D0}'),
+    ('=', '>', '\u{21D2}'),
+    ('=', '=', '\u{21D4}'),
+    ('F', 'A', '\u{2200}'),
+    ('d', 'P', '\u{2202}'),
+    ('T', 'E', '\u{2203}'),
+    ('/', '0', '\u{2205}'),
+    ('D', 'E', '\u{2206}'),
+    ('N', 'B', '\u{2207}'),
+    ('(', '-', '\u{2208}'),
+    ('-', ')', '\u{220B}'),
+    ('*', 'P', '\u{220F}'),
+    ('+', 'Z', '\u{2211}'),
+    ('-', '2', '\u{2212}'),
+    ('-', '+', '\u{2213}'),
+    ('*', '-', '\u{2217}'),
+    ('O', 'b', '\u{2218}'),
+    ('S', 'b', '\u{2219}'),
+    ('R', 'T', '\u{221A}'),
+    ('0', '(', '\u{221D}'),
+    ('0', '0', '\u{221E}'),
+    ('-', 'L', '\u{221F}'),
+    ('-', 'V', '\u{2220}'),
+    ('P', 'P', '\u{2225}'),
+    ('A', 'N', '\u{2227}'),
+    ('O', 'R', '\u{2228}'),
+    ('(', 'U', '\u{2229}'),
+    (')', 'U', '\u{222A}'),
+    ('I', 'n', '\u{222B}'),
+    ('D', 'I', '\u{222C}'),
+    ('I', 'o', '\u{222E}'),
+    ('.', ':', '\u{2234}'),
+    (':', '.', '\u{2235}'),
+    (':', 'R', '\u{2236}'),
+    (':', ':', '\u{2237}'),
+    ('?', '1', '\u{223C}'),
+    ('C', 'G', '\u{223E}'),
+    ('?', '-', '\u{2243}'),
+    ('?', '=', '\u{2245}'),
+    ('?', '2', '\u{2248}'),
+    ('=', '?', '\u{224C}'),
+    ('H', 'I', '\u{2253}'),
+    ('!', '=', '\u{2260}'),
+    ('=', '3', '\u{2261}'),
+    ('=', '<', '\u{2264}'),
+    ('>', '=', '\u{2265}'),
+    ('<', '*', '\u{226A}'),
+    ('*', '>', '\u{226B}'),
+    ('!', '<', '\u{226E}'),
+    ('!', '>', '\u{226F}'),
+    ('(', 'C', '\u{2282}'),
+    (')', 'C', '\u{2283}'),
+    ('(', '_', '\u{2286}'),
+    (')', '_', '\u{2287}'),
+    ('0', '.', '\u{2299}'),
+    ('0', '2', '\u{229A}'),
+    ('-', 'T', '\u{22A5}'),
+    ('.', 'P', '\u{22C5}'),
+    (':', '3', '\u{22EE}'),
+    ('.', '3', '\u{22EF}'),
+    ('E', 'h', '\u{2302}'),
+    ('<', '7', '\u{2308}'),
+    ('>', '7', '\u{2309}'),
+    ('7', '<', '\u{230A}'),
+    ('7', '>', '\u{230B}'),
+    ('h', 'h', '\u{2500}'),
+    ('v', 'v', '\u{2502}'),
+    ('O', 'S', '\u{25A1}'),
+    ('D', 'b', '\u{25C6}'),
+    ('D', 'w', '\u{25C7}'),
+    ('L', 'Z', '\u{25CA}'),
+    ('0', 'm', '\u{25CB}'),
+    ('0', 'o', '\u{25CE}'),
+    ('0', 'M', '\u{25CF}'),
+    ('*', '2', '\u{2605}'),
+    ('*', '1', '\u{2606}'),
+    ('c', 'H', '\u{2660}'),
+    ('c', 'S', '\u{2663}'),
+    ('c', 'D', '\u{2666}'),
+    ('M', 'd', '\u{2669}'),
+    ('M', '8', '\u{266A}'),
+    ('M', '2', '\u{266B}'),
+    ('M', 'b', '\u{266D}'),
+    ('M', 'x', '\u{266E}'),
+    ('M', 'X', '\u{266F}'),
+    ('O', 'K', '\u{2713}'),
+    ('X', 'X', '\u{2717}'),
+    ('-', 'X', '\u{2720}'),
+    ('0', 'S', '\u{2070}'),
+    ('4', 'S', '\u{2074}'),
+    ('5', 'S', '\u{2075}'),
+    ('6', 'S', '\u{2076}'),
+    ('7', 'S', '\u{2077}'),
+    ('8', 'S', '\u{2078}'),
+    ('9', 'S', '\u{2079}'),
+    ('+', 'S', '\u{207A}'),
+    ('-', 'S', '\u{207B}'),
+    ('=', 'S', '\u{207C}'),
+    ('(', 'S', '\u{207D}'),
+    (')', 'S', '\u{207E}'),
+    ('n', 'S', '\u{207F}'),
+    ('0', 's', '\u{2080}'),
+    ('1', 's', '\u{2081}'),
+    ('2', 's', '\u{2082}'),
+    ('3', 's', '\u{2083}'),
+    ('4', 's', '\u{2084}'),
+    ('5', 's', '\u{2085}'),
+    ('6', 's', '\u{2086}'),
+    ('7', 's', '\u{2087}'),
+    ('8', 's', '\u{2088}'),
+    ('9', 's', '\u{2089}'),
+    ('+', 's', '\u{208A}'),
+    ('-', 's', '\u{208B}'),
+    ('=', 's', '\u{208C}'),
+    ('(', 's', '\u{208D}'),
+    (')', 's', '\u{208E}'),
+    ('1', '3', '\u{2153}'),
+    ('2', '3', '\u{2154}'),
+    ('1', '5', '\u{2155}'),
+    ('1', '8', '\u{215B}'),
+    ('3', '8', '\u{215C}'),
+    ('5', '8', '\u{215D}'),
+    ('7', '8', '\u{215E}'),
+];
+
+/// Digraph table, with user defined digraphs taking priority over the built in ones
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Digraphs {
+    custom: Vec<(char, char, char)>,
+}
+
+impl Digraphs {
+    pub fn new() -> Self {
+        Self { custom: Vec::new() }
+    }
+
+    /// Look up digraph, trying the characters in reverse order if not found
+    pub fn get(&self, a: char, b: char) -> Option<char> {
+        self.get_exact(a, b).or_else(|| self.get_exact(b, a))
+    }
+
+    fn get_exact(&self, a: char, b: char) -> Option<char> {
+        self.custom
+            .iter()
+            .chain(DIGRAPHS_DEFAULT.iter())
+            .find(|(other_a, other_b, _)| *other_a == a && *other_b == b)
+            .map(|(_, _, c)| *c)
+    }
+
+    /// Define a digraph, replacing any existing definition
+    pub fn insert(&mut self, a: char, b: char, c: char) {
+        self.custom
+            .retain(|(other_a, other_b, _)| *other_a != a || *other_b != b);
+        self.custom.push((a, b, c));
+    }
+
+    /// Define digraphs using the arguments of `:digraphs`, like `e: 235 a* 945`.
+    /// Returns false if the arguments are invalid, definitions before the error are kept.
+    pub fn define(&mut self, args: &str) -> bool {
+        let mut words = args.split_whitespace();
+        while let Some(chars) = words.next() {
+            let mut chars_iter = chars.chars();
+            let (a, b) = match (chars_iter.next(), chars_iter.next(), chars_iter.next()) {
+                (Some(a), Some(b), None) => (a, b),
+                _ => return false,
+            };
+            match words
+                .next()
+                .and_then(|number| number.parse::<u32>().ok())
+                .and_then(char::from_u32)
+            {
+                Some(c) => self.insert(a, b, c),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Iterate over all digraphs, user defined ones first, for listing with `:digraphs`
+    pub fn iter(&self) -> impl Iterator<Item = (char, char, char)> + '_ {
+        self.custom.iter().chain(DIGRAPHS_DEFAULT.iter()).copied()
+    }
+}
@@ -0,0 +1,662 @@
+use core::mem;
+
+use crate::{
+    BufferCommand, Event, Key, KeyCode, Modifiers, Motion, Mouse, MouseAction, MouseButton, Parser,
+    ScrollPosition, TextObject, WindowCommand, WindowFocus, Word, WriteRequest,
+};
+
+/// Number of kill ring entries
+pub const EMACS_KILL_RING_LEN: usize = 10;
+
+/// Register of the first kill ring entry, the other entries use the characters following it.
+/// These are private use characters, so they are not used by other parsers or by the user.
+pub const EMACS_KILL_RING_REGISTER: char = '\u{E000}';
+
+/// Register yanked with the middle mouse button, the primary selection
+pub const EMACS_MOUSE_REGISTER: char = '*';
+
+/// Lines or columns scrolled by the mouse wheel, like emacs's default
+/// `mouse-wheel-scroll-amount`
+pub const EMACS_MOUSE_SCROLL_LINES: usize = 1;
+
+/// Register used for a kill ring entry
+fn kill_ring_register(index: usize) -> char {
+    let index = index.checked_rem(EMACS_KILL_RING_LEN).unwrap_or(0);
+    u32::try_from(index)
+        .ok()
+        .and_then(|index| u32::from(EMACS_KILL_RING_REGISTER).checked_add(index))
+        .and_then(char::from_u32)
+        .unwrap_or(EMACS_KILL_RING_REGISTER)
+}
+
+/// Prefix key waiting for the rest of a key sequence
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EmacsPrefix {
+    /// `C-x`
+    CtrlX,
+    /// Escape, which makes the next character a meta key
+    Escape,
+}
+
+/// Previous command, used to group inserts, append kills, and cycle yanks
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EmacsLast {
+    Other,
+    Insert,
+    Kill,
+    /// Yank of the kill ring entry this many entries before the newest one
+    Yank(usize),
+}
+
+/// Parser for emacs keybindings. Kills are yanked to the registers from
+/// [`EMACS_KILL_RING_REGISTER`], and appending kills use [`Event::YankAppend`].
+#[derive(Debug)]
+pub struct EmacsParser {
+    pub prefix: Option<EmacsPrefix>,
+    /// Argument from `C-u` or `M-` digits, which repeats the next command
+    pub argument: Option<usize>,
+    /// True if digits were typed for the argument
+    argument_digits: bool,
+    /// Index of the newest kill ring entry
+    kill_index: usize,
+    /// Number of kill ring entries in use
+    kill_len: usize,
+    last: EmacsLast,
+    /// True if reset interrupted inserting, so the change is finished on the next key
+    finish_change: bool,
+}
+
+impl EmacsParser {
+    pub fn new() -> Self {
+        Self {
+            prefix: None,
+            argument: None,
+            argument_digits: false,
+            // The first kill uses the first register
+            kill_index: EMACS_KILL_RING_LEN.saturating_sub(1),
+            kill_len: 0,
+            last: EmacsLast::Other,
+            finish_change: false,
+        }
+    }
+
+    /// Yank the selection to the kill ring, appending to the newest entry if the last
+    /// command was a kill
+    fn kill_yank<F: FnMut(Event)>(&mut self, last: EmacsLast, callback: &mut F) {
+        if last == EmacsLast::Kill && self.kill_len > 0 {
+            callback(Event::YankAppend {
+                register: kill_ring_register(self.kill_index),
+            });
+        } else {
+            self.kill_index = self
+                .kill_index
+                .saturating_add(1)
+                .checked_rem(EMACS_KILL_RING_LEN)
+                .unwrap_or(0);
+            self.kill_len = self.kill_len.saturating_add(1).min(EMACS_KILL_RING_LEN);
+            callback(Event::Yank {
+                register: kill_ring_register(self.kill_index),
+            });
+        }
+    }
+
+    /// Kill the text selected by repeating motion, or the selection if motion is None
+    fn kill<F: FnMut(Event)>(
+        &mut self,
+        last: EmacsLast,
+        motion: Option<(Motion, usize)>,
+        callback: &mut F,
+    ) {
+        callback(Event::ChangeStart);
+        if let Some((motion, count)) = motion {
+            callback(Event::SelectStart);
+            for _ in 0..count {
+                callback(Event::Motion(motion));
+            }
+        }
+        self.kill_yank(last, callback);
+        callback(Event::Delete);
+        callback(Event::SelectClear);
+        callback(Event::ChangeFinish);
+        self.last = EmacsLast::Kill;
+    }
+
+    /// Put a kill ring entry before the cursor, leaving the cursor after it. If replace
+    /// is true, the text of the last yank is deleted first.
+    fn yank<F: FnMut(Event)>(&mut self, back: usize, replace: bool, callback: &mut F) {
+        if self.kill_len == 0 {
+            return;
+        }
+        let back = back.checked_rem(self.kill_len).unwrap_or(0);
+        let index = self
+            .kill_index
+            .saturating_add(EMACS_KILL_RING_LEN)
+            .saturating_sub(back);
+        callback(Event::ChangeStart);
+        if replace {
+            callback(Event::DeleteLastPut);
+        }
+        callback(Event::PutAndMove {
+            register: kill_ring_register(index),
+            after: false,
+        });
+        callback(Event::ChangeFinish);
+        self.last = EmacsLast::Yank(back);
+    }
+
+    /// Click to set point, drag or right click to set the region, double or triple click
+    /// to select a word or line, and middle click to yank the primary selection
+    fn mouse<F: FnMut(Event)>(mouse: Mouse, selection: bool, callback: &mut F) {
+        let position = Motion::Position {
+            line: mouse.line,
+            index: mouse.index,
+        };
+        match mouse.action {
+            MouseAction::Press {
+                button: MouseButton::Left,
+                clicks,
+            } => {
+                if selection {
+                    callback(Event::SelectClear);
+                }
+                callback(Event::Motion(position));
+                match clicks {
+                    0 | 1 => {}
+                    2 => {
+                        callback(Event::SelectStart);
+                        callback(Event::SelectTextObject(
+                            TextObject::Word(Word::Lower),
+                            false,
+                        ));
+                    }
+                    _ => callback(Event::SelectLineStart),
+                }
+            }
+            MouseAction::Drag(MouseButton::Left)
+            | MouseAction::Press {
+                button: MouseButton::Right,
+                ..
+            } => {
+                if !selection {
+                    callback(Event::SelectStart);
+                }
+                callback(Event::Motion(position));
+            }
+            MouseAction::Press {
+                button: MouseButton::Middle,
+                ..
+            } => {
+                if selection {
+                    callback(Event::SelectClear);
+                }
+                callback(Event::Motion(position));
+                callback(Event::ChangeStart);
+                callback(Event::PutAndMove {
+                    register: EMACS_MOUSE_REGISTER,
+                    after: false,
+                });
+                callback(Event::ChangeFinish);
+            }
+            MouseAction::ScrollUp => callback(Event::ScrollUp(EMACS_MOUSE_SCROLL_LINES)),
+            MouseAction::ScrollDown => callback(Event::ScrollDown(EMACS_MOUSE_SCROLL_LINES)),
+            MouseAction::ScrollLeft => callback(Event::ScrollLeft(EMACS_MOUSE_SCROLL_LINES)),
+            MouseAction::ScrollRight => callback(Event::ScrollRight(EMACS_MOUSE_SCROLL_LINES)),
+            _ => {}
+        }
+    }
+
+    /// Handle key after `C-x`
+    fn ctrl_x<F: FnMut(Event)>(&mut self, key: Key, count: usize, callback: &mut F) {
+        match (key.modifiers, key.code) {
+            // Save file
+            (Modifiers::CTRL, KeyCode::Char('s')) => callback(Event::Write(WriteRequest {
+                range: None,
+                path: None,
+                force: false,
+                modified_only: true,
+                save_as: false,
+            })),
+            // Quit
+            (Modifiers::CTRL, KeyCode::Char('c')) => callback(Event::Quit { force: false }),
+            // Undo
+            (Modifiers::NONE, KeyCode::Char('u')) => {
+                for _ in 0..count {
+                    callback(Event::Undo);
+                }
+            }
+            // Select all, with the cursor at the start
+            (Modifiers::NONE, KeyCode::Char('h')) => {
+                callback(Event::Motion(Motion::GotoEof));
+                callback(Event::SelectStart);
+                callback(Event::Motion(Motion::GotoLine(1)));
+            }
+            // Windows
+            (Modifiers::NONE, KeyCode::Char('0')) => {
+                callback(Event::Window(WindowCommand::Close { force: false }));
+            }
+            (Modifiers::NONE, KeyCode::Char('1')) => {
+                callback(Event::Window(WindowCommand::Only { force: false }));
+            }
+            (Modifiers::NONE, KeyCode::Char('2')) => {
+                callback(Event::Window(WindowCommand::Split {
+                    size: None,
+                    path: None,
+                }));
+            }
+            (Modifiers::NONE, KeyCode::Char('3')) => {
+                callback(Event::Window(WindowCommand::VerticalSplit {
+                    size: None,
+                    path: None,
+                }));
+            }
+            (Modifiers::NONE, KeyCode::Char('o')) => {
+                callback(Event::Window(WindowCommand::Focus(WindowFocus::Next)));
+            }
+            // Buffers
+            (Modifiers::NONE, KeyCode::Char('k')) => {
+                callback(Event::Buffer(BufferCommand::Delete {
+                    number: None,
+                    force: false,
+                }));
+            }
+            (Modifiers::NONE, KeyCode::Left) => {
+                callback(Event::Buffer(BufferCommand::Previous(count)));
+            }
+            (Modifiers::NONE, KeyCode::Right) => {
+                callback(Event::Buffer(BufferCommand::Next(count)));
+            }
+            //TODO: commands that prompt, like C-x C-f and C-x b
+            _ => {
+                log::info!("TODO: C-x {:?}", key);
+            }
+        }
+    }
+}
+
+impl Parser for EmacsParser {
+    fn reset(&mut self) {
+        self.prefix = None;
+        self.argument = None;
+        self.argument_digits = false;
+        // The callback is not available here, so the change is finished on the next key
+        let last = mem::replace(&mut self.last, EmacsLast::Other);
+        self.finish_change |= last == EmacsLast::Insert;
+    }
+
+    fn paste<F: FnMut(Event)>(&mut self, text: &str, selection: bool, mut callback: F) {
+        // Pasting cancels prefixes and arguments, and is undone separately from typing
+        let last = mem::replace(&mut self.last, EmacsLast::Other);
+        if last == EmacsLast::Insert || mem::take(&mut self.finish_change) {
+            callback(Event::ChangeFinish);
+        }
+        self.prefix = None;
+        self.argument = None;
+        self.argument_digits = false;
+
+        // Like a yank, the text is inserted at point and the region is deactivated
+        if selection {
+            callback(Event::SelectClear);
+        }
+        callback(Event::ChangeStart);
+        callback(Event::InsertText(text.into()));
+        callback(Event::ChangeFinish);
+        callback(Event::Redraw);
+    }
+
+    fn parse<F: FnMut(Event)>(&mut self, key: Key, selection: bool, mut callback: F) {
+        // Normalize key, so we don't deal with control characters below
+        let mut key = key.normalize();
+        // Escape followed by a character is the same as a meta key
+        if self.prefix == Some(EmacsPrefix::Escape) {
+            self.prefix = None;
+            if key.modifiers.is_empty() && matches!(key.code, KeyCode::Char(_)) {
+                key.modifiers = Modifiers::ALT;
+            }
+        }
+
+        if mem::take(&mut self.finish_change) {
+            callback(Event::ChangeFinish);
+        }
+        let last = mem::replace(&mut self.last, EmacsLast::Other);
+        // Characters typed together are undone together
+        if last == EmacsLast::Insert
+            && (self.prefix.is_some()
+                || !matches!(
+                    (key.modifiers, key.code),
+                    (Modifiers::NONE, KeyCode::Char(_))
+                ))
+        {
+            callback(Event::ChangeFinish);
+        }
+
+        // Mouse keys cancel prefixes and arguments
+        if let KeyCode::Mouse(mouse) = key.code {
+            self.prefix = None;
+            self.argument = None;
+            self.argument_digits = false;
+            EmacsParser::mouse(mouse, selection, &mut callback);
+            callback(Event::Redraw);
+            return;
+        }
+
+        // Collect argument
+        match (key.modifiers, key.code) {
+            (Modifiers::CTRL, KeyCode::Char('u')) if self.prefix.is_none() => {
+                self.argument = Some(match self.argument {
+                    Some(argument) if !self.argument_digits => argument.saturating_mul(4),
+                    _ => 4,
+                });
+                self.argument_digits = false;
+                return;
+            }
+            (Modifiers::NONE, KeyCode::Char(c @ '0'..='9'))
+            | (Modifiers::ALT, KeyCode::Char(c @ '0'..='9'))
+                if self.prefix.is_none()
+                    && (self.argument.is_some() || key.modifiers == Modifiers::ALT) =>
+            {
+                let digit = c.to_digit(10).unwrap_or(0) as usize;
+                self.argument = Some(match self.argument {
+                    Some(argument) if self.argument_digits => {
+                        argument.saturating_mul(10).saturating_add(digit)
+                    }
+                    _ => digit,
+                });
+                self.argument_digits = true;
+                return;
+            }
+            _ => {}
+        }
+        let has_argument = self.argument.is_some();
+        let count = self.argument.take().unwrap_or(1);
+        self.argument_digits = false;
+
+        if self.prefix.take() == Some(EmacsPrefix::CtrlX) {
+            self.ctrl_x(key, count, &mut callback);
+            callback(Event::Redraw);
+            return;
+        }
+
+        let mut motion = |motion| {
+            for _ in 0..count {
+                callback(Event::Motion(motion));
+            }
+        };
+        match (key.modifiers, key.code) {
+            // Motions
+            (Modifiers::CTRL, KeyCode::Char('f')) | (Modifiers::NONE, KeyCode::Right) => {
+                motion(Motion::Right);
+            }
+            (Modifiers::CTRL, KeyCode::Char('b')) | (Modifiers::NONE, KeyCode::Left) => {
+                motion(Motion::Left);
+            }
+            (Modifiers::CTRL, KeyCode::Char('n')) | (Modifiers::NONE, KeyCode::Down) => {
+                motion(Motion::Down);
+            }
+            (Modifiers::CTRL, KeyCode::Char('p')) | (Modifiers::NONE, KeyCode::Up) => {
+                motion(Motion::Up);
+            }
+            (Modifiers::CTRL, KeyCode::Char('a')) | (Modifiers::NONE, KeyCode::Home) => {
+                motion(Motion::Home);
+            }
+            (Modifiers::CTRL, KeyCode::Char('e')) | (Modifiers::NONE, KeyCode::End) => {
+                motion(Motion::End);
+            }
+            (Modifiers::CTRL, KeyCode::Char('v')) | (Modifiers::NONE, KeyCode::PageDown) => {
+                motion(Motion::PageDown);
+            }
+            (Modifiers::ALT, KeyCode::Char('v')) | (Modifiers::NONE, KeyCode::PageUp) => {
+                motion(Motion::PageUp);
+            }
+            // Like emacs, move after the end of the word
+            (Modifiers::ALT, KeyCode::Char('f')) => {
+                for _ in 0..count {
+                    callback(Event::Motion(Motion::NextWordEnd(Word::Lower)));
+                    callback(Event::Motion(Motion::RightInLine));
+                }
+            }
+            (Modifiers::ALT, KeyCode::Char('b')) => motion(Motion::PreviousWordStart(Word::Lower)),
+            (Modifiers::ALT, KeyCode::Char('m')) => motion(Motion::SoftHome),
+            (Modifiers::ALT, KeyCode::Char('<')) => callback(Event::Motion(Motion::GotoLine(1))),
+            (Modifiers::ALT, KeyCode::Char('>')) => callback(Event::Motion(Motion::GotoEof)),
+            (Modifiers::CTRL, KeyCode::Char('l')) => callback(Event::ScrollCursor {
+                position: ScrollPosition::Center,
+                reset_column: false,
+            }),
+
+            // Inserting
+            (Modifiers::NONE, KeyCode::Char(c)) => {
+                if last != EmacsLast::Insert {
+                    callback(Event::ChangeStart);
+                }
+                for _ in 0..count {
+                    callback(Event::Insert(c));
+                }
+                self.last = EmacsLast::Insert;
+            }
+            (Modifiers::NONE, KeyCode::Enter) | (Modifiers::CTRL, KeyCode::Char('j')) => {
+                callback(Event::ChangeStart);
+                for _ in 0..count {
+                    callback(Event::NewLine);
+                }
+                callback(Event::ChangeFinish);
+            }
+            // Open line after cursor
+            (Modifiers::CTRL, KeyCode::Char('o')) => {
+                callback(Event::ChangeStart);
+                for _ in 0..count {
+                    callback(Event::NewLine);
+                    callback(Event::Motion(Motion::Left));
+                }
+                callback(Event::ChangeFinish);
+            }
+            (Modifiers::NONE, KeyCode::Tab) => {
+                callback(Event::ChangeStart);
+                for _ in 0..count {
+                    callback(Event::Insert('\t'));
+                }
+                callback(Event::ChangeFinish);
+            }
+
+            // Deleting
+            (Modifiers::CTRL, KeyCode::Char('d')) | (Modifiers::NONE, KeyCode::Delete) => {
+                callback(Event::ChangeStart);
+                for _ in 0..count {
+                    callback(Event::Delete);
+                }
+                callback(Event::ChangeFinish);
+            }
+            (Modifiers::NONE, KeyCode::Backspace) => {
+                callback(Event::ChangeStart);
+                for _ in 0..count {
+                    callback(Event::Backspace);
+                }
+                callback(Event::ChangeFinish);
+            }
+
+            // Killing and yanking
+            (Modifiers::CTRL, KeyCode::Char('k')) => {
+                if has_argument {
+                    // Kill whole lines, including line breaks
+                    callback(Event::ChangeStart);
+                    callback(Event::SelectStart);
+                    for _ in 0..count {
+                        callback(Event::Motion(Motion::Down));
+                    }
+                    callback(Event::Motion(Motion::Home));
+                    self.kill_yank(last, &mut callback);
+                    callback(Event::Delete);
+                    callback(Event::SelectClear);
+                    callback(Event::ChangeFinish);
+                    self.last = EmacsLast::Kill;
+                } else {
+                    // Kill the rest of the line, or the line break at the end of a line
+                    self.kill(last, Some((Motion::EndOrNextLine, 1)), &mut callback);
+                }
+            }
+            (Modifiers::ALT, KeyCode::Char('d')) => {
+                self.kill(
+                    last,
+                    Some((Motion::NextWordEnd(Word::Lower), count)),
+                    &mut callback,
+                );
+            }
+            (Modifiers::ALT, KeyCode::Backspace) | (Modifiers::ALT, KeyCode::Delete) => {
+                self.kill(
+                    last,
+                    Some((Motion::PreviousWordStart(Word::Lower), count)),
+                    &mut callback,
+                );
+            }
+            // Kill region
+            (Modifiers::CTRL, KeyCode::Char('w')) => {
+                if selection {
+                    self.kill(last, None, &mut callback);
+                }
+            }
+            // Copy region
+            (Modifiers::ALT, KeyCode::Char('w')) => {
+                if selection {
+                    self.kill_yank(last, &mut callback);
+                    callback(Event::SelectClear);
+                    self.last = EmacsLast::Kill;
+                }
+            }
+            (Modifiers::CTRL, KeyCode::Char('y')) => {
+                self.yank(count.saturating_sub(1), false, &mut callback);
+            }
+            // Replace the last yank with an older kill
+            (Modifiers::ALT, KeyCode::Char('y')) => {
+                if let EmacsLast::Yank(back) = last {
+                    self.yank(back.saturating_add(count), true, &mut callback);
+                }
+            }
+
+            // Mark
+            (Modifiers::CTRL, KeyCode::Char(' ' | '@')) => {
+                if selection {
+                    callback(Event::SelectClear);
+                }
+                callback(Event::SelectStart);
+            }
+            // Cancel
+            (Modifiers::CTRL, KeyCode::Char('g')) => {
+                callback(Event::SelectClear);
+                callback(Event::Escape);
+            }
+            // Meta prefix
+            (Modifiers::NONE, KeyCode::Escape) => {
+                self.prefix = Some(EmacsPrefix::Escape);
+            }
+            // Undo
+            (Modifiers::CTRL, KeyCode::Char('/' | '_')) => {
+                for _ in 0..count {
+                    callback(Event::Undo);
+                }
+            }
+            (Modifiers::CTRL, KeyCode::Char('x')) => {
+                self.prefix = Some(EmacsPrefix::CtrlX);
+                // Keep argument for the command after C-x
+                if has_argument {
+                    self.argument = Some(count);
+                }
+            }
+            //TODO: search, transpose, case, and other commands
+            _ => {
+                log::info!("TODO: emacs key {:?}", key);
+            }
+        }
+
+        //TODO: optimize redraw
+        callback(Event::Redraw);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn parse_keys(parser: &mut EmacsParser, keys: &str) -> Vec<Event> {
+        let mut events = Vec::new();
+        parser.parse_keys(keys, false, |event| events.push(event));
+        events
+    }
+
+    #[test]
+    fn reset_finishes_insert() {
+        let mut parser = EmacsParser::new();
+        let events = parse_keys(&mut parser, "ab");
+        assert!(matches!(events.first(), Some(Event::ChangeStart)));
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::ChangeFinish)));
+
+        parser.reset();
+        let events = parse_keys(&mut parser, "c");
+        assert!(matches!(
+            events.as_slice(),
+            [
+                Event::ChangeFinish,
+                Event::ChangeStart,
+                Event::Insert('c'),
+                ..
+            ]
+        ));
+    }
+
+    #[test]
+    fn kill_line() {
+        let events = parse_keys(&mut EmacsParser::new(), "<C-k>");
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::Motion(Motion::EndOrNextLine))));
+    }
+
+    #[test]
+    fn yank_pop() {
+        let register = |index| kill_ring_register(index);
+        let mut parser = EmacsParser::new();
+        let events = parse_keys(&mut parser, "<M-d><M-d>x<M-d>");
+        let yanks = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Yank { register } => Some((*register, false)),
+                Event::YankAppend { register } => Some((*register, true)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Consecutive kills append to the same entry
+        assert_eq!(
+            yanks,
+            [
+                (register(0), false),
+                (register(0), true),
+                (register(1), false)
+            ]
+        );
+
+        let events = parse_keys(&mut parser, "<C-y><M-y><M-y><M-y>");
+        assert!(!events.iter().any(|event| matches!(event, Event::Undo)));
+        let puts = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::PutAndMove { register, .. } => Some(*register),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // The first yank puts the newest kill, then M-y cycles through older ones
+        assert_eq!(puts, [register(1), register(0), register(1), register(0)]);
+        let replaced = events
+            .iter()
+            .filter(|event| matches!(event, Event::DeleteLastPut))
+            .count();
+        assert_eq!(replaced, 3);
+
+        // Kill ring registers are not named registers
+        for index in 0..EMACS_KILL_RING_LEN {
+            assert!(!register(index).is_ascii());
+        }
+        assert_eq!(register(EMACS_KILL_RING_LEN), EMACS_KILL_RING_REGISTER);
+    }
+}
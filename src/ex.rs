@@ -0,0 +1,363 @@
+use alloc::string::String;
+
+/// Base of a line address in an ex command range
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExLine {
+    /// Line number, starting at 1
+    Number(usize),
+    /// Line at cursor, `.`
+    Current,
+    /// Last line, `$`
+    Last,
+    /// Line of a mark, `'x`
+    Mark(char),
+}
+
+/// Line address in an ex command range, like `.+3` or `'a-1`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ExAddress {
+    pub line: ExLine,
+    /// Lines added to the base line
+    pub offset: isize,
+}
+
+impl ExAddress {
+    /// Resolve to a line number starting at 1, given the current and last line numbers and
+    /// a function returning mark line numbers. Returns None if the line is invalid.
+    pub fn resolve<F: Fn(char) -> Option<usize>>(
+        &self,
+        current: usize,
+        last: usize,
+        mark: F,
+    ) -> Option<usize> {
+        let base = match self.line {
+            ExLine::Number(line) => line,
+            ExLine::Current => current,
+            ExLine::Last => last,
+            ExLine::Mark(c) => mark(c)?,
+        };
+        base.checked_add_signed(self.offset)
+            .filter(|&line| line <= last)
+    }
+}
+
+/// Range of lines in an ex command, like `%` or `1,5`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ExRange {
+    pub start: ExAddress,
+    /// End of the range, None if only one line was provided
+    pub end: Option<ExAddress>,
+}
+
+impl ExRange {
+    /// Range of all lines, `%`
+    pub fn all() -> Self {
+        Self {
+            start: ExAddress {
+                line: ExLine::Number(1),
+                offset: 0,
+            },
+            end: Some(ExAddress {
+                line: ExLine::Last,
+                offset: 0,
+            }),
+        }
+    }
+}
+
+/// An ex command line split into range, name, bang, and arguments, like `:1,5w! file`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ExCommand<'a> {
+    pub range: Option<ExRange>,
+    /// Command name as typed, which may be abbreviated
+    pub name: &'a str,
+    /// True if the name was followed by `!`
+    pub bang: bool,
+    /// Arguments with surrounding whitespace removed
+    pub args: &'a str,
+}
+
+impl<'a> ExCommand<'a> {
+    /// Parse a command line, returns None if the range is invalid
+    pub fn parse(value: &'a str) -> Option<Self> {
+        let mut rest = value.trim_start_matches(|c: char| c == ':' || c.is_whitespace());
+        let range = parse_range(&mut rest)?;
+        rest = rest.trim_start();
+
+        // Names are letters, or a single other character like & or <
+        let end = match rest.find(|c: char| !c.is_ascii_alphabetic()) {
+            Some(0) => rest.chars().next().map_or(0, char::len_utf8),
+            Some(end) => end,
+            None => rest.len(),
+        };
+        let (name, rest) = rest.split_at(end);
+        let (bang, args) = match rest.strip_prefix('!') {
+            Some(args) => (true, args),
+            None => (false, rest),
+        };
+        Some(Self {
+            range,
+            name,
+            bang,
+            args: args.trim(),
+        })
+    }
+
+    /// Returns true if this runs the named command, which may be abbreviated to
+    /// `min_len` characters
+    pub fn is(&self, name: &str, min_len: usize) -> bool {
+        self.name.len() >= min_len.max(1) && name.starts_with(self.name)
+    }
+
+    /// Arguments, or None if there are none
+    pub fn args(&self) -> Option<&'a str> {
+        Some(self.args).filter(|args| !args.is_empty())
+    }
+
+    /// Count provided as a single line number before the name, like `:10split`
+    pub fn count(&self) -> Option<usize> {
+        match self.range {
+            Some(ExRange {
+                start:
+                    ExAddress {
+                        line: ExLine::Number(count),
+                        offset: 0,
+                    },
+                end: None,
+            }) => Some(count),
+            _ => None,
+        }
+    }
+}
+
+/// File writing requested by an ex command or `ZZ`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WriteRequest {
+    /// Lines to write, None for all lines
+    pub range: Option<ExRange>,
+    /// File to write, None for the current file
+    pub path: Option<String>,
+    /// Write even if the file is read only or exists, from `!`
+    pub force: bool,
+    /// Only write if the buffer was modified, like `:update` and `:x`
+    pub modified_only: bool,
+    /// Use the path as the current file after writing, like `:saveas`
+    pub save_as: bool,
+}
+
+/// Parse a range and remove it from value, returns None if it is invalid
+fn parse_range(value: &mut &str) -> Option<Option<ExRange>> {
+    if let Some(rest) = value.strip_prefix('%') {
+        *value = rest;
+        return Some(Some(ExRange::all()));
+    }
+
+    let mut start = match parse_address(value)? {
+        Some(address) => address,
+        // A missing address before a separator is the current line
+        None if value.starts_with([',', ';']) => ExAddress {
+            line: ExLine::Current,
+            offset: 0,
+        },
+        None => return Some(None),
+    };
+    let mut end = None;
+    while let Some(rest) = value.strip_prefix([',', ';']) {
+        *value = rest;
+        // A missing address after a separator is the current line
+        let address = parse_address(value)?.unwrap_or(ExAddress {
+            line: ExLine::Current,
+            offset: 0,
+        });
+        // Like vim, only the last two addresses are used
+        if let Some(end) = end.replace(address) {
+            start = end;
+        }
+    }
+    Some(Some(ExRange { start, end }))
+}
+
+/// Parse an address and remove it from value, returns None if it is invalid
+fn parse_address(value: &mut &str) -> Option<Option<ExAddress>> {
+    let mut chars = value.chars();
+    let line = match chars.next() {
+        Some('0'..='9') => {
+            let (number, rest) = split_number(value);
+            *value = rest;
+            Some(ExLine::Number(number?))
+        }
+        Some('.') => {
+            *value = chars.as_str();
+            Some(ExLine::Current)
+        }
+        Some('$') => {
+            *value = chars.as_str();
+            Some(ExLine::Last)
+        }
+        Some('\'') => {
+            let c = chars.next()?;
+            *value = chars.as_str();
+            Some(ExLine::Mark(c))
+        }
+        //TODO: search addresses like /pattern/
+        _ => None,
+    };
+
+    let mut offset: Option<isize> = None;
+    while let Some(negative) = match value.chars().next() {
+        Some('+') => Some(false),
+        Some('-') => Some(true),
+        _ => None,
+    } {
+        let digits = value.get(1..).unwrap_or("");
+        let (number, rest) = split_number(digits);
+        let number = match number {
+            Some(number) => isize::try_from(number).ok()?,
+            // A sign without a number means one
+            None if rest.len() == digits.len() => 1,
+            None => return None,
+        };
+        *value = rest;
+        let total = offset.unwrap_or(0);
+        offset = Some(if negative {
+            total.checked_sub(number)?
+        } else {
+            total.checked_add(number)?
+        });
+    }
+
+    Some(match (line, offset) {
+        (None, None) => None,
+        (line, offset) => Some(ExAddress {
+            // Offsets without a line are relative to the current line
+            line: line.unwrap_or(ExLine::Current),
+            offset: offset.unwrap_or(0),
+        }),
+    })
+}
+
+/// Split leading digits from value, returning the number if there are digits
+fn split_number(value: &str) -> (Option<usize>, &str) {
+    let end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (digits, rest) = value.split_at(end);
+    (digits.parse().ok(), rest)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn address(line: ExLine, offset: isize) -> ExAddress {
+        ExAddress { line, offset }
+    }
+
+    #[test]
+    fn is() {
+        let command = ExCommand::parse("sp").unwrap();
+        assert!(command.is("split", 2));
+        assert!(!command.is("split", 3));
+        assert!(!ExCommand::parse("spx").unwrap().is("split", 2));
+        assert!(!ExCommand::parse("splitx").unwrap().is("split", 2));
+        // An empty name never matches
+        assert!(!ExCommand::parse("").unwrap().is("split", 0));
+
+        let command = ExCommand::parse(":1,5w! file ").unwrap();
+        assert!(command.is("write", 1));
+        assert!(command.bang);
+        assert_eq!(command.args(), Some("file"));
+        let command = ExCommand::parse("w !cmd").unwrap();
+        assert!(!command.bang);
+        assert_eq!(command.args(), Some("!cmd"));
+    }
+
+    #[test]
+    fn parse_range() {
+        let range = |value| {
+            let mut value = value;
+            let range = super::parse_range(&mut value);
+            (range, value)
+        };
+        assert_eq!(range("%d"), (Some(Some(ExRange::all())), "d"));
+        assert_eq!(range("d"), (Some(None), "d"));
+        assert_eq!(
+            range(".,$y"),
+            (
+                Some(Some(ExRange {
+                    start: address(ExLine::Current, 0),
+                    end: Some(address(ExLine::Last, 0)),
+                })),
+                "y"
+            )
+        );
+        assert_eq!(
+            range("'<,'>s"),
+            (
+                Some(Some(ExRange {
+                    start: address(ExLine::Mark('<'), 0),
+                    end: Some(address(ExLine::Mark('>'), 0)),
+                })),
+                "s"
+            )
+        );
+        // Missing addresses around a separator are the current line
+        assert_eq!(
+            range(",5"),
+            (
+                Some(Some(ExRange {
+                    start: address(ExLine::Current, 0),
+                    end: Some(address(ExLine::Number(5), 0)),
+                })),
+                ""
+            )
+        );
+        // Only the last two addresses are used
+        assert_eq!(
+            range("1;2;3p"),
+            (
+                Some(Some(ExRange {
+                    start: address(ExLine::Number(2), 0),
+                    end: Some(address(ExLine::Number(3), 0)),
+                })),
+                "p"
+            )
+        );
+        assert_eq!(range("'").0, None);
+    }
+
+    #[test]
+    fn parse_address() {
+        let parse = |value| {
+            let mut value = value;
+            let address = super::parse_address(&mut value);
+            (address, value)
+        };
+        assert_eq!(
+            parse("10d"),
+            (Some(Some(address(ExLine::Number(10), 0))), "d")
+        );
+        assert_eq!(
+            parse("'a-1"),
+            (Some(Some(address(ExLine::Mark('a'), -1))), "")
+        );
+        // A sign without a number means one, and offsets without a line are relative to
+        // the current line
+        assert_eq!(parse("+-+3"), (Some(Some(address(ExLine::Current, 3))), ""));
+        assert_eq!(parse("$--"), (Some(Some(address(ExLine::Last, -2))), ""));
+        assert_eq!(parse("w"), (Some(None), "w"));
+        assert_eq!(parse("99999999999999999999").0, None);
+        assert_eq!(parse(".+99999999999999999999").0, None);
+    }
+
+    #[test]
+    fn resolve() {
+        let resolve = |address: ExAddress| address.resolve(5, 10, |c| (c == 'a').then_some(2));
+        assert_eq!(resolve(address(ExLine::Current, 2)), Some(7));
+        assert_eq!(resolve(address(ExLine::Last, 1)), None);
+        assert_eq!(resolve(address(ExLine::Mark('a'), -2)), Some(0));
+        assert_eq!(resolve(address(ExLine::Mark('b'), 0)), None);
+        assert_eq!(resolve(address(ExLine::Number(1), -2)), None);
+    }
+}
@@ -0,0 +1,279 @@
+use alloc::vec::Vec;
+
+use crate::Motion;
+
+/// A range of lines that can be closed to show as one line
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Fold {
+    /// First line of the fold
+    pub start: usize,
+    /// Last line of the fold, inclusive
+    pub end: usize,
+    /// True if the fold is shown as one line
+    pub closed: bool,
+}
+
+impl Fold {
+    /// Returns true if the fold includes the line
+    pub fn contains(&self, line: usize) -> bool {
+        self.start <= line && line <= self.end
+    }
+
+    fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Manually created folds, which hosts can use for fold events and to move over closed folds
+#[derive(Clone, Debug, Default)]
+pub struct Folds {
+    /// Folds sorted by start line, nested folds are after the folds containing them
+    folds: Vec<Fold>,
+}
+
+impl Folds {
+    pub fn new() -> Self {
+        Self { folds: Vec::new() }
+    }
+
+    /// All folds, sorted by start line
+    pub fn folds(&self) -> &[Fold] {
+        &self.folds
+    }
+
+    /// Create a closed fold from start to end, like `zf`
+    pub fn create(&mut self, start: usize, end: usize) {
+        let fold = Fold {
+            start: start.min(end),
+            end: start.max(end),
+            closed: true,
+        };
+        self.folds.push(fold);
+        self.sort();
+    }
+
+    /// Delete the innermost fold at line, like `zd`. Returns false if there is no fold.
+    pub fn delete(&mut self, line: usize) -> bool {
+        match self.innermost(line, |_| true) {
+            Some(index) => {
+                self.folds.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Delete all folds, like `zE`
+    pub fn clear(&mut self) {
+        self.folds.clear();
+    }
+
+    /// Open the closed fold shown at line, like `zo`. Returns false if there is none.
+    pub fn open(&mut self, line: usize) -> bool {
+        match self.outermost_closed(line) {
+            Some(index) => self.set_closed(index, false),
+            None => false,
+        }
+    }
+
+    /// Close the innermost open fold containing the line as it is shown, like `zc`.
+    /// Returns false if there is none.
+    pub fn close(&mut self, line: usize) -> bool {
+        let shown = self.closed_range(line);
+        let index = self.innermost(line, |fold| {
+            !fold.closed && shown.is_none_or(|(start, end)| fold.start <= start && end <= fold.end)
+        });
+        match index {
+            Some(index) => self.set_closed(index, true),
+            None => false,
+        }
+    }
+
+    /// Open the fold at line if it is closed, otherwise close it, like `za`
+    pub fn toggle(&mut self, line: usize) -> bool {
+        self.open(line) || self.close(line)
+    }
+
+    /// Open all folds, like `zR`
+    pub fn open_all(&mut self) {
+        for fold in self.folds.iter_mut() {
+            fold.closed = false;
+        }
+    }
+
+    /// Close all folds, like `zM`
+    pub fn close_all(&mut self) {
+        for fold in self.folds.iter_mut() {
+            fold.closed = true;
+        }
+    }
+
+    /// Range of lines shown as one line because of a closed fold, if the line is in one
+    pub fn closed_range(&self, line: usize) -> Option<(usize, usize)> {
+        self.outermost_closed(line)
+            .and_then(|index| self.folds.get(index))
+            .map(|fold| (fold.start, fold.end))
+    }
+
+    /// Line after moving down once, skipping lines hidden by closed folds.
+    /// The result may be past the last line.
+    pub fn down(&self, line: usize) -> usize {
+        let end = self.closed_range(line).map_or(line, |(_, end)| end);
+        end.saturating_add(1)
+    }
+
+    /// Line after moving up once, moving to the start of closed folds
+    pub fn up(&self, line: usize) -> usize {
+        let start = self.closed_range(line).map_or(line, |(start, _)| start);
+        let up = start.saturating_sub(1);
+        self.closed_range(up).map_or(up, |(start, _)| start)
+    }
+
+    /// Line after a fold motion from line, or None if it is not a fold motion or there is
+    /// no such fold
+    pub fn motion(&self, motion: Motion, line: usize) -> Option<usize> {
+        match motion {
+            Motion::Down => Some(self.down(line)),
+            Motion::Up => Some(self.up(line)),
+            // Start of the next fold, a closed fold counts as one fold
+            Motion::NextFoldStart => {
+                let after = self.closed_range(line).map_or(line, |(_, end)| end);
+                self.folds
+                    .iter()
+                    .map(|fold| fold.start)
+                    .filter(|&start| start > after)
+                    .min()
+            }
+            // End of the previous fold, a closed fold counts as one fold
+            Motion::PreviousFoldEnd => {
+                let before = self.closed_range(line).map_or(line, |(start, _)| start);
+                self.folds
+                    .iter()
+                    .map(|fold| fold.end)
+                    .filter(|&end| end < before)
+                    .max()
+            }
+            // Start of the current open fold, or the fold containing it if already there
+            Motion::FoldStart => self
+                .innermost(line, |fold| fold.start < line)
+                .and_then(|index| self.folds.get(index))
+                .map(|fold| fold.start),
+            // End of the current open fold, or the fold containing it if already there
+            Motion::FoldEnd => self
+                .innermost(line, |fold| fold.end > line)
+                .and_then(|index| self.folds.get(index))
+                .map(|fold| fold.end),
+            _ => None,
+        }
+    }
+
+    /// Adjust folds after lines are inserted at line
+    pub fn insert_lines(&mut self, line: usize, count: usize) {
+        for fold in self.folds.iter_mut() {
+            if fold.start >= line {
+                fold.start = fold.start.saturating_add(count);
+            }
+            if fold.end >= line {
+                fold.end = fold.end.saturating_add(count);
+            }
+        }
+        self.sort();
+    }
+
+    /// Adjust folds after lines from start to end, inclusive, are removed.
+    /// Folds inside the removed lines are deleted.
+    pub fn remove_lines(&mut self, start: usize, end: usize) {
+        let count = end.saturating_sub(start).saturating_add(1);
+        self.folds
+            .retain(|fold| !(start <= fold.start && fold.end <= end));
+        let shift = |line: usize| {
+            if line > end {
+                line.saturating_sub(count)
+            } else if line >= start {
+                start
+            } else {
+                line
+            }
+        };
+        for fold in self.folds.iter_mut() {
+            fold.start = shift(fold.start);
+            fold.end = if fold.end >= start && fold.end <= end {
+                start.saturating_sub(1).max(fold.start)
+            } else {
+                shift(fold.end)
+            };
+        }
+        // Folds that started in the removed lines can now start on the same line
+        self.sort();
+    }
+
+    /// Sort by start line, with larger folds first, so they are before the folds they
+    /// contain
+    fn sort(&mut self) {
+        self.folds
+            .sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+    }
+
+    /// Index of the smallest fold containing line that matches the filter
+    fn innermost<F: Fn(&Fold) -> bool>(&self, line: usize, filter: F) -> Option<usize> {
+        self.folds
+            .iter()
+            .enumerate()
+            .filter(|(_, fold)| fold.contains(line) && filter(fold))
+            .min_by_key(|(_, fold)| fold.len())
+            .map(|(index, _)| index)
+    }
+
+    /// Index of the largest closed fold containing line
+    fn outermost_closed(&self, line: usize) -> Option<usize> {
+        self.folds
+            .iter()
+            .enumerate()
+            .filter(|(_, fold)| fold.closed && fold.contains(line))
+            .max_by_key(|(_, fold)| fold.len())
+            .map(|(index, _)| index)
+    }
+
+    fn set_closed(&mut self, index: usize, closed: bool) -> bool {
+        match self.folds.get_mut(index) {
+            Some(fold) => {
+                fold.closed = closed;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(folds: &Folds) -> Vec<(usize, usize)> {
+        folds
+            .folds()
+            .iter()
+            .map(|fold| (fold.start, fold.end))
+            .collect()
+    }
+
+    #[test]
+    fn remove_lines_sorted() {
+        let mut folds = Folds::new();
+        folds.create(2, 5);
+        folds.create(4, 9);
+        folds.remove_lines(1, 3);
+        assert_eq!(ranges(&folds), [(1, 6), (1, 2)]);
+    }
+
+    #[test]
+    fn insert_lines_sorted() {
+        let mut folds = Folds::new();
+        folds.create(2, 4);
+        folds.create(4, 6);
+        folds.insert_lines(4, 2);
+        assert_eq!(ranges(&folds), [(2, 6), (6, 8)]);
+        folds.insert_lines(0, 1);
+        assert_eq!(ranges(&folds), [(3, 7), (7, 9)]);
+    }
+}
@@ -0,0 +1,783 @@
+use alloc::string::String;
+use core::mem;
+
+use crate::{
+    vi::{cmdline_key, cmdline_paste},
+    Event, History, Key, KeyCode, Modifiers, Motion, Mouse, MouseAction, MouseButton, Parser,
+    ScrollPosition, SearchOffset, SearchRequest, SelectionCommand, TextObject, Word,
+};
+
+pub const HELIX_DEFAULT_REGISTER: char = '"';
+
+/// Register put with the middle mouse button, the primary selection
+pub const HELIX_MOUSE_REGISTER: char = '*';
+
+/// Lines scrolled by the mouse wheel, like helix's default `scroll-lines`
+pub const HELIX_MOUSE_SCROLL_LINES: usize = 3;
+
+/// What the prompt is used for
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HelixPrompt {
+    /// Search, with `/` or `?`
+    Search { forwards: bool },
+    /// Select matches inside selections, with `s`
+    Select,
+    /// Split selections at matches, with `S`
+    Split,
+    /// Keep selections that match, or do not match, with `K` and `Alt-K`
+    Keep { matching: bool },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HelixMode {
+    /// Normal mode, where motions replace the selection
+    Normal,
+    /// Select mode, where motions extend the selection
+    Select,
+    /// Insert mode
+    Insert,
+    /// Waiting for another character to complete command
+    Extra(char),
+    /// Typing a pattern, cursor is a byte index into value
+    Prompt {
+        prompt: HelixPrompt,
+        value: String,
+        cursor: usize,
+    },
+}
+
+/// Text object for a character typed after `mi` or `ma`
+fn text_object(c: char) -> Option<TextObject> {
+    Some(match c {
+        '<' | '>' => TextObject::AngleBrackets,
+        '{' | '}' => TextObject::CurlyBrackets,
+        '"' => TextObject::DoubleQuotes,
+        'p' => TextObject::Paragraph,
+        '(' | ')' | 'b' => TextObject::Parentheses,
+        's' => TextObject::Sentence,
+        '\'' => TextObject::SingleQuotes,
+        '[' | ']' => TextObject::SquareBrackets,
+        't' => TextObject::Tag,
+        '`' => TextObject::Ticks,
+        'w' => TextObject::Word(Word::Lower),
+        'W' => TextObject::Word(Word::Upper),
+        _ => return None,
+    })
+}
+
+/// Parser for selection first keybindings like Kakoune and Helix, where motions select text
+/// and commands act on the selection
+#[derive(Debug)]
+pub struct HelixParser {
+    pub mode: HelixMode,
+    pub count: Option<usize>,
+    pub register: Option<char>,
+    pub search_history: History,
+    /// Prompt history, shared by selection prompts
+    pub select_history: History,
+    /// True if the selection was made by line with `x`
+    line_selection: bool,
+    /// Mode to return to after a command waiting for another character or a prompt
+    extra_mode: HelixMode,
+}
+
+impl HelixParser {
+    pub fn new() -> Self {
+        Self {
+            mode: HelixMode::Normal,
+            count: None,
+            register: None,
+            search_history: History::new(),
+            select_history: History::new(),
+            line_selection: false,
+            extra_mode: HelixMode::Normal,
+        }
+    }
+
+    /// Move, replacing the selection in normal mode and extending it in select mode.
+    /// If select is true, the text moved over is selected in normal mode.
+    fn motion<F: FnMut(Event)>(&mut self, motion: Motion, select: bool, callback: &mut F) {
+        let count = self.count.take().unwrap_or(1);
+        if self.mode == HelixMode::Select {
+            for _ in 0..count {
+                callback(Event::Motion(motion));
+            }
+            return;
+        }
+
+        callback(Event::SelectClear);
+        self.line_selection = false;
+        // Only the text moved over by the last motion is selected
+        for _ in 1..count {
+            callback(Event::Motion(motion));
+        }
+        if select {
+            callback(Event::SelectStart);
+        }
+        callback(Event::Motion(motion));
+    }
+
+    /// Click to place the cursor, drag to select, and double or triple click to select
+    /// a word or line, in every mode except prompts
+    fn mouse<F: FnMut(Event)>(&mut self, mouse: Mouse, selection: bool, callback: &mut F) {
+        let position = Motion::Position {
+            line: mouse.line,
+            index: mouse.index,
+        };
+        match mouse.action {
+            MouseAction::ScrollUp => callback(Event::ScrollUp(HELIX_MOUSE_SCROLL_LINES)),
+            MouseAction::ScrollDown => callback(Event::ScrollDown(HELIX_MOUSE_SCROLL_LINES)),
+            _ if matches!(self.mode, HelixMode::Prompt { .. }) => {}
+            MouseAction::Press {
+                button: MouseButton::Left,
+                clicks,
+            } => {
+                // Clicking cancels commands waiting for a character
+                if matches!(self.mode, HelixMode::Extra(_)) {
+                    self.reset_command();
+                }
+                if selection {
+                    callback(Event::SelectClear);
+                }
+                self.line_selection = false;
+                callback(Event::Motion(position));
+                match clicks {
+                    0 | 1 => {}
+                    2 => {
+                        callback(Event::SelectStart);
+                        callback(Event::SelectTextObject(
+                            TextObject::Word(Word::Lower),
+                            false,
+                        ));
+                    }
+                    _ => {
+                        callback(Event::SelectLineStart);
+                        self.line_selection = true;
+                    }
+                }
+            }
+            MouseAction::Drag(MouseButton::Left) => {
+                HelixParser::ensure_selection(selection, callback);
+                callback(Event::Motion(position));
+            }
+            MouseAction::Press {
+                button: MouseButton::Middle,
+                ..
+            } => {
+                if matches!(self.mode, HelixMode::Extra(_)) {
+                    self.reset_command();
+                }
+                if selection {
+                    callback(Event::SelectClear);
+                }
+                callback(Event::Motion(position));
+                callback(Event::ChangeStart);
+                callback(Event::Put {
+                    register: HELIX_MOUSE_REGISTER,
+                    after: false,
+                });
+                callback(Event::ChangeFinish);
+            }
+            _ => {}
+        }
+    }
+
+    /// Make sure there is a selection, the character at cursor is selected if there is none
+    fn ensure_selection<F: FnMut(Event)>(selection: bool, callback: &mut F) {
+        if !selection {
+            callback(Event::SelectStart);
+        }
+    }
+
+    /// Open prompt, returning to the current mode when it closes
+    fn prompt(&mut self, prompt: HelixPrompt) {
+        match prompt {
+            HelixPrompt::Search { .. } => self.search_history.reset_recall(),
+            _ => self.select_history.reset_recall(),
+        }
+        self.extra_mode = mem::replace(
+            &mut self.mode,
+            HelixMode::Prompt {
+                prompt,
+                value: String::new(),
+                cursor: 0,
+            },
+        );
+    }
+
+    /// Close prompt, returning to the mode it was opened from
+    fn close_prompt(&mut self) {
+        self.mode = mem::replace(&mut self.extra_mode, HelixMode::Normal);
+        self.count = None;
+        self.register = None;
+    }
+
+    /// Finish command, returning to normal mode unless extending selections
+    fn reset_command(&mut self) {
+        self.count = None;
+        self.register = None;
+        if matches!(self.mode, HelixMode::Extra(_)) {
+            self.mode = mem::replace(&mut self.extra_mode, HelixMode::Normal);
+        }
+        if self.mode != HelixMode::Select {
+            self.mode = HelixMode::Normal;
+        }
+    }
+
+    fn normal<F: FnMut(Event)>(&mut self, key: Key, selection: bool, callback: &mut F) {
+        let register = self.register.unwrap_or(HELIX_DEFAULT_REGISTER);
+        match (key.modifiers, key.code) {
+            (Modifiers::NONE, KeyCode::Left) => self.motion(Motion::Left, false, callback),
+            (Modifiers::NONE, KeyCode::Down) => self.motion(Motion::Down, false, callback),
+            (Modifiers::NONE, KeyCode::Up) => self.motion(Motion::Up, false, callback),
+            (Modifiers::NONE, KeyCode::Right) => self.motion(Motion::Right, false, callback),
+            (Modifiers::NONE, KeyCode::Home) => self.motion(Motion::Home, false, callback),
+            (Modifiers::NONE, KeyCode::End) => self.motion(Motion::End, false, callback),
+            (Modifiers::NONE, KeyCode::PageUp) => self.motion(Motion::PageUp, false, callback),
+            (Modifiers::NONE, KeyCode::PageDown) => self.motion(Motion::PageDown, false, callback),
+            (Modifiers::NONE, KeyCode::Escape) => {
+                if self.mode == HelixMode::Select {
+                    self.mode = HelixMode::Normal;
+                } else {
+                    callback(Event::SelectClear);
+                }
+                self.reset_command();
+                callback(Event::Escape);
+            }
+            // Flip selection
+            (Modifiers::ALT, KeyCode::Char(';')) => {
+                callback(Event::Selection(SelectionCommand::Flip));
+            }
+            // Ensure selections are forward
+            (Modifiers::ALT, KeyCode::Char(':')) => {
+                callback(Event::Selection(SelectionCommand::Forward));
+            }
+            // Copy selection to previous line
+            (Modifiers::ALT, KeyCode::Char('C')) => {
+                for _ in 0..self.count.take().unwrap_or(1) {
+                    callback(Event::Selection(SelectionCommand::AddAbove));
+                }
+            }
+            // Remove primary selection
+            (Modifiers::ALT, KeyCode::Char(',')) => {
+                callback(Event::Selection(SelectionCommand::RemovePrimary));
+            }
+            // Split selections into lines
+            (Modifiers::ALT, KeyCode::Char('s')) => {
+                callback(Event::Selection(SelectionCommand::SplitLines));
+            }
+            // Remove selections that match
+            (Modifiers::ALT, KeyCode::Char('K')) => {
+                self.prompt(HelixPrompt::Keep { matching: false });
+            }
+            (Modifiers::NONE, KeyCode::Char(c)) => match c {
+                // Count of next action
+                '0'..='9' if c != '0' || self.count.is_some() => {
+                    let number = c.to_digit(10).unwrap_or(0) as usize;
+                    self.count = Some(match self.count {
+                        Some(count) => count.saturating_mul(10).saturating_add(number),
+                        None => number,
+                    });
+                }
+                'h' => self.motion(Motion::Left, false, callback),
+                'j' => self.motion(Motion::Down, false, callback),
+                'k' => self.motion(Motion::Up, false, callback),
+                'l' => self.motion(Motion::Right, false, callback),
+                'w' => self.motion(Motion::NextWordStart(Word::Lower), true, callback),
+                'W' => self.motion(Motion::NextWordStart(Word::Upper), true, callback),
+                'b' => self.motion(Motion::PreviousWordStart(Word::Lower), true, callback),
+                'B' => self.motion(Motion::PreviousWordStart(Word::Upper), true, callback),
+                'e' => self.motion(Motion::NextWordEnd(Word::Lower), true, callback),
+                'E' => self.motion(Motion::NextWordEnd(Word::Upper), true, callback),
+                'n' => self.motion(Motion::NextSearch, true, callback),
+                'N' => self.motion(Motion::PreviousSearch, true, callback),
+                // Select line, or extend to the next line if a line is selected
+                'x' => {
+                    let count = self.count.take().unwrap_or(1);
+                    if !(selection && self.line_selection) {
+                        callback(Event::SelectClear);
+                        callback(Event::SelectLineStart);
+                        self.line_selection = true;
+                        for _ in 1..count {
+                            callback(Event::Motion(Motion::Down));
+                        }
+                    } else {
+                        for _ in 0..count {
+                            callback(Event::Motion(Motion::Down));
+                        }
+                    }
+                }
+                // Select all
+                '%' => {
+                    callback(Event::SelectClear);
+                    callback(Event::Motion(Motion::GotoEof));
+                    callback(Event::SelectStart);
+                    callback(Event::Motion(Motion::GotoLine(1)));
+                }
+                // Collapse selection
+                ';' => {
+                    callback(Event::SelectClear);
+                    self.line_selection = false;
+                }
+                // Keep primary selection
+                ',' => callback(Event::Selection(SelectionCommand::KeepPrimary)),
+                // Rotate primary selection
+                '(' => callback(Event::Selection(SelectionCommand::Rotate {
+                    forwards: false,
+                })),
+                ')' => callback(Event::Selection(SelectionCommand::Rotate {
+                    forwards: true,
+                })),
+                // Copy selection to next or previous line
+                'C' => {
+                    for _ in 0..self.count.take().unwrap_or(1) {
+                        callback(Event::Selection(SelectionCommand::AddBelow));
+                    }
+                }
+                // Extend mode
+                'v' => {
+                    if self.mode == HelixMode::Select {
+                        self.mode = HelixMode::Normal;
+                    } else {
+                        HelixParser::ensure_selection(selection, callback);
+                        self.mode = HelixMode::Select;
+                    }
+                }
+                // Delete
+                'd' => {
+                    callback(Event::ChangeStart);
+                    HelixParser::ensure_selection(selection, callback);
+                    callback(Event::Yank { register });
+                    callback(Event::Delete);
+                    callback(Event::SelectClear);
+                    callback(Event::ChangeFinish);
+                    self.mode = HelixMode::Normal;
+                }
+                // Change
+                'c' => {
+                    callback(Event::ChangeStart);
+                    HelixParser::ensure_selection(selection, callback);
+                    callback(Event::Yank { register });
+                    callback(Event::Delete);
+                    callback(Event::SelectClear);
+                    self.mode = HelixMode::Insert;
+                }
+                // Yank
+                'y' => {
+                    HelixParser::ensure_selection(selection, callback);
+                    callback(Event::Yank { register });
+                }
+                // Paste after or before
+                'p' | 'P' => {
+                    callback(Event::ChangeStart);
+                    for _ in 0..self.count.take().unwrap_or(1) {
+                        callback(Event::Put {
+                            register,
+                            after: c == 'p',
+                        });
+                    }
+                    callback(Event::ChangeFinish);
+                }
+                // Replace selection with register
+                'R' => {
+                    callback(Event::ChangeStart);
+                    HelixParser::ensure_selection(selection, callback);
+                    callback(Event::Delete);
+                    callback(Event::SelectClear);
+                    callback(Event::Put {
+                        register,
+                        after: false,
+                    });
+                    callback(Event::ChangeFinish);
+                    self.mode = HelixMode::Normal;
+                }
+                // Insert before selection
+                'i' => {
+                    callback(Event::ChangeStart);
+                    if selection {
+                        // Move cursor to the start of the selection
+                        callback(Event::Selection(SelectionCommand::Forward));
+                        callback(Event::Selection(SelectionCommand::Flip));
+                    }
+                    callback(Event::SelectClear);
+                    self.mode = HelixMode::Insert;
+                }
+                // Insert after selection
+                'a' => {
+                    callback(Event::ChangeStart);
+                    if selection {
+                        // Move cursor to the end of the selection
+                        callback(Event::Selection(SelectionCommand::Forward));
+                    }
+                    callback(Event::SelectClear);
+                    callback(Event::Motion(Motion::RightInLine));
+                    self.mode = HelixMode::Insert;
+                }
+                // Insert at start of line
+                'I' => {
+                    callback(Event::ChangeStart);
+                    callback(Event::SelectClear);
+                    callback(Event::Motion(Motion::SoftHome));
+                    self.mode = HelixMode::Insert;
+                }
+                // Insert at end of line
+                'A' => {
+                    callback(Event::ChangeStart);
+                    callback(Event::SelectClear);
+                    callback(Event::Motion(Motion::End));
+                    self.mode = HelixMode::Insert;
+                }
+                // Open line below
+                'o' => {
+                    callback(Event::ChangeStart);
+                    callback(Event::SelectClear);
+                    callback(Event::Motion(Motion::End));
+                    callback(Event::NewLine);
+                    self.mode = HelixMode::Insert;
+                }
+                // Open line above
+                'O' => {
+                    callback(Event::ChangeStart);
+                    callback(Event::SelectClear);
+                    callback(Event::Motion(Motion::Home));
+                    callback(Event::NewLine);
+                    callback(Event::Motion(Motion::Up));
+                    self.mode = HelixMode::Insert;
+                }
+                'u' => {
+                    for _ in 0..self.count.take().unwrap_or(1) {
+                        callback(Event::Undo);
+                    }
+                }
+                'U' => {
+                    for _ in 0..self.count.take().unwrap_or(1) {
+                        callback(Event::Redo);
+                    }
+                }
+                '>' => {
+                    callback(Event::ChangeStart);
+                    HelixParser::ensure_selection(selection, callback);
+                    callback(Event::ShiftRight);
+                    callback(Event::ChangeFinish);
+                }
+                '<' => {
+                    callback(Event::ChangeStart);
+                    HelixParser::ensure_selection(selection, callback);
+                    callback(Event::ShiftLeft);
+                    callback(Event::ChangeFinish);
+                }
+                '~' => {
+                    callback(Event::ChangeStart);
+                    HelixParser::ensure_selection(selection, callback);
+                    callback(Event::SwapCase);
+                    callback(Event::ChangeFinish);
+                }
+                // Prompts
+                '/' | '?' => self.prompt(HelixPrompt::Search { forwards: c == '/' }),
+                's' => self.prompt(HelixPrompt::Select),
+                'S' => self.prompt(HelixPrompt::Split),
+                'K' => self.prompt(HelixPrompt::Keep { matching: true }),
+                // Commands waiting for another character
+                'f' | 'F' | 't' | 'T' | 'g' | 'm' | 'r' | 'z' | '"' => {
+                    self.extra_mode = mem::replace(&mut self.mode, HelixMode::Extra(c));
+                }
+                _ => {
+                    //TODO: more commands
+                    log::info!("TODO: helix key {:?}", key);
+                }
+            },
+            _ => {
+                //TODO: more keys
+                log::info!("TODO: helix key {:?}", key);
+            }
+        }
+    }
+
+    fn extra<F: FnMut(Event)>(&mut self, extra: char, key: Key, selection: bool, callback: &mut F) {
+        // Return to the mode from before the command
+        self.mode = mem::replace(&mut self.extra_mode, HelixMode::Normal);
+        let c = match (key.modifiers, key.code) {
+            (Modifiers::NONE, KeyCode::Char(c)) => c,
+            _ => {
+                self.reset_command();
+                return;
+            }
+        };
+        match extra {
+            // Select to character
+            'f' => self.motion(Motion::NextChar(c), true, callback),
+            'F' => self.motion(Motion::PreviousChar(c), true, callback),
+            't' => self.motion(Motion::NextCharTill(c), true, callback),
+            'T' => self.motion(Motion::PreviousCharTill(c), true, callback),
+            // Goto
+            'g' => match c {
+                'g' => {
+                    let line = self.count.take().unwrap_or(1);
+                    self.motion(Motion::GotoLine(line), false, callback);
+                }
+                'e' => self.motion(Motion::GotoEof, false, callback),
+                'h' => self.motion(Motion::Home, false, callback),
+                'l' => self.motion(Motion::End, false, callback),
+                's' => self.motion(Motion::SoftHome, false, callback),
+                't' => self.motion(Motion::ScreenHigh, false, callback),
+                'c' => self.motion(Motion::ScreenMiddle, false, callback),
+                'b' => self.motion(Motion::ScreenLow, false, callback),
+                _ => log::info!("TODO: helix key g{:?}", key),
+            },
+            // Match text objects
+            'm' => match c {
+                'i' | 'a' => {
+                    self.extra_mode = mem::replace(
+                        &mut self.mode,
+                        HelixMode::Extra(if c == 'i' { 'i' } else { 'a' }),
+                    );
+                    return;
+                }
+                _ => log::info!("TODO: helix key m{:?}", key),
+            },
+            // Select inside or around text object, after m
+            'i' | 'a' => {
+                if let Some(text_object) = text_object(c) {
+                    callback(Event::SelectClear);
+                    callback(Event::SelectTextObject(text_object, extra == 'a'));
+                }
+            }
+            // Replace selected characters
+            'r' => {
+                callback(Event::ChangeStart);
+                HelixParser::ensure_selection(selection, callback);
+                callback(Event::Selection(SelectionCommand::ReplaceChars(c)));
+                callback(Event::ChangeFinish);
+            }
+            // View
+            'z' => {
+                let position = match c {
+                    'z' | 'c' => ScrollPosition::Center,
+                    't' => ScrollPosition::Top,
+                    'b' => ScrollPosition::Bottom,
+                    _ => {
+                        self.reset_command();
+                        return;
+                    }
+                };
+                callback(Event::ScrollCursor {
+                    position,
+                    reset_column: false,
+                });
+            }
+            // Select register
+            '"' => {
+                self.register = Some(c);
+                return;
+            }
+            _ => {}
+        }
+        self.reset_command();
+    }
+}
+
+impl Parser for HelixParser {
+    fn reset(&mut self) {
+        self.mode = HelixMode::Normal;
+        self.count = None;
+        self.register = None;
+        self.line_selection = false;
+        self.extra_mode = HelixMode::Normal;
+    }
+
+    fn parse<F: FnMut(Event)>(&mut self, key: Key, selection: bool, mut callback: F) {
+        // Normalize key, so we don't deal with control characters below
+        let key = key.normalize();
+        let callback = &mut callback;
+        if let KeyCode::Mouse(mouse) = key.code {
+            self.mouse(mouse, selection, callback);
+            callback(Event::Redraw);
+            return;
+        }
+        match self.mode {
+            HelixMode::Normal | HelixMode::Select => self.normal(key, selection, callback),
+            HelixMode::Extra(extra) => self.extra(extra, key, selection, callback),
+            HelixMode::Insert => match (key.modifiers, key.code) {
+                (Modifiers::NONE, KeyCode::Backspace) => callback(Event::Backspace),
+                (Modifiers::NONE, KeyCode::Char(c)) => callback(Event::Insert(c)),
+                (Modifiers::NONE, KeyCode::Delete) => callback(Event::Delete),
+                (Modifiers::NONE, KeyCode::Down) => callback(Event::Motion(Motion::Down)),
+                (Modifiers::NONE, KeyCode::End) => callback(Event::Motion(Motion::End)),
+                (Modifiers::NONE, KeyCode::Enter) => callback(Event::NewLine),
+                (Modifiers::NONE, KeyCode::Escape) => {
+                    callback(Event::ChangeFinish);
+                    self.reset();
+                }
+                (Modifiers::NONE, KeyCode::Home) => callback(Event::Motion(Motion::Home)),
+                (Modifiers::NONE, KeyCode::Left) => callback(Event::Motion(Motion::LeftInLine)),
+                (Modifiers::NONE, KeyCode::PageDown) => callback(Event::Motion(Motion::PageDown)),
+                (Modifiers::NONE, KeyCode::PageUp) => callback(Event::Motion(Motion::PageUp)),
+                (Modifiers::NONE, KeyCode::Right) => callback(Event::Motion(Motion::RightInLine)),
+                (Modifiers::NONE, KeyCode::Tab) => callback(Event::Insert('\t')),
+                (Modifiers::NONE, KeyCode::Up) => callback(Event::Motion(Motion::Up)),
+                _ => {}
+            },
+            HelixMode::Prompt {
+                prompt,
+                ref mut value,
+                ref mut cursor,
+            } => match (key.modifiers, key.code) {
+                (Modifiers::NONE, KeyCode::Escape) => self.close_prompt(),
+                (Modifiers::NONE, KeyCode::Enter) => {
+                    let pattern = value.clone();
+                    self.close_prompt();
+                    // Selections are replaced, so they are no longer whole lines
+                    self.line_selection = false;
+                    match prompt {
+                        HelixPrompt::Search { forwards } => {
+                            self.search_history.push(pattern.clone());
+                            // Helix patterns have no offsets, so they are used as typed
+                            callback(Event::SetSearch(SearchRequest {
+                                pattern,
+                                forwards,
+                                offset: SearchOffset::default(),
+                                next: None,
+                            }));
+                            self.motion(Motion::NextSearch, true, callback);
+                        }
+                        HelixPrompt::Select => {
+                            self.select_history.push(pattern.clone());
+                            callback(Event::Selection(SelectionCommand::Select(pattern)));
+                        }
+                        HelixPrompt::Split => {
+                            self.select_history.push(pattern.clone());
+                            callback(Event::Selection(SelectionCommand::Split(pattern)));
+                        }
+                        HelixPrompt::Keep { matching } => {
+                            self.select_history.push(pattern.clone());
+                            callback(Event::Selection(SelectionCommand::Keep {
+                                pattern,
+                                matching,
+                            }));
+                        }
+                    }
+                }
+                _ => {
+                    let history = match prompt {
+                        HelixPrompt::Search { .. } => &mut self.search_history,
+                        _ => &mut self.select_history,
+                    };
+                    if !cmdline_key(value, cursor, history, key) {
+                        self.close_prompt();
+                    }
+                }
+            },
+        }
+
+        //TODO: optimize redraw
+        callback(Event::Redraw);
+    }
+
+    fn paste<F: FnMut(Event)>(&mut self, text: &str, _selection: bool, mut callback: F) {
+        match self.mode {
+            // Like P, put the text before the selection, cancelling any pending command
+            HelixMode::Normal | HelixMode::Select | HelixMode::Extra(_) => {
+                callback(Event::ChangeStart);
+                callback(Event::PutText(text.into()));
+                callback(Event::ChangeFinish);
+                self.reset_command();
+            }
+            HelixMode::Insert => callback(Event::InsertText(text.into())),
+            HelixMode::Prompt {
+                prompt,
+                ref mut value,
+                ref mut cursor,
+            } => {
+                let history = match prompt {
+                    HelixPrompt::Search { .. } => &mut self.search_history,
+                    _ => &mut self.select_history,
+                };
+                cmdline_paste(value, cursor, history, text);
+            }
+        }
+
+        //TODO: optimize redraw
+        callback(Event::Redraw);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn extra_keeps_select_mode() {
+        for keys in ["vfx", "vtx", "vgl", "vmiw", "v\"a", "vz<Esc>"] {
+            let mut parser = HelixParser::new();
+            parser.parse_keys(keys, false, |_| {});
+            assert_eq!(parser.mode, HelixMode::Select, "{keys}");
+        }
+
+        let mut parser = HelixParser::new();
+        parser.parse_keys("fx", false, |_| {});
+        assert_eq!(parser.mode, HelixMode::Normal);
+    }
+
+    #[test]
+    fn prompt_returns_to_mode() {
+        for (keys, mode) in [
+            ("v/a<CR>", HelixMode::Select),
+            ("vsa<Esc>", HelixMode::Select),
+            ("v?<BS>", HelixMode::Select),
+            ("/a<CR>", HelixMode::Normal),
+            ("Sa<Esc>", HelixMode::Normal),
+        ] {
+            let mut parser = HelixParser::new();
+            parser.parse_keys(keys, false, |_| {});
+            assert_eq!(parser.mode, mode, "{keys}");
+        }
+    }
+
+    #[test]
+    fn search_pattern_as_typed() {
+        let mut events = Vec::new();
+        HelixParser::new().parse_keys("/a/e;b<CR>", false, |event| events.push(event));
+        let expected = SearchRequest {
+            pattern: "a/e;b".into(),
+            forwards: true,
+            offset: SearchOffset::default(),
+            next: None,
+        };
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::SetSearch(request) if *request == expected)));
+    }
+
+    #[test]
+    fn insert_at_selection_edges() {
+        // Cursor after typing i or a, with a selection from anchor to cursor
+        let insert_at = |keys: &str, anchor: usize, cursor: usize| {
+            let (mut anchor, mut cursor) = (Some(anchor), cursor);
+            HelixParser::new().parse_keys(keys, true, |event| match event {
+                Event::Selection(SelectionCommand::Forward) => {
+                    if let Some(start) = anchor.filter(|&start| start > cursor) {
+                        anchor = Some(cursor);
+                        cursor = start;
+                    }
+                }
+                Event::Selection(SelectionCommand::Flip) => {
+                    if let Some(start) = anchor.replace(cursor) {
+                        cursor = start;
+                    }
+                }
+                Event::SelectClear => anchor = None,
+                Event::Motion(Motion::RightInLine) => cursor += 1,
+                _ => {}
+            });
+            cursor
+        };
+        // Forward selection
+        assert_eq!(insert_at("i", 2, 5), 2);
+        assert_eq!(insert_at("a", 2, 5), 6);
+        // Backward selection
+        assert_eq!(insert_at("i", 5, 2), 2);
+        assert_eq!(insert_at("a", 5, 2), 6);
+    }
+}
@@ -0,0 +1,177 @@
+use alloc::{string::String, vec::Vec};
+
+/// Default number of entries kept by a [`History`]
+pub const HISTORY_DEFAULT_MAX_LEN: usize = 50;
+
+/// Command line history, with prefix filtered recall
+#[derive(Clone, Debug)]
+pub struct History {
+    entries: Vec<String>,
+    max_len: usize,
+    /// Index of entry being recalled, if any
+    index: Option<usize>,
+    /// Text typed before recall started, used as prefix filter
+    typed: Option<String>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            max_len: HISTORY_DEFAULT_MAX_LEN,
+            index: None,
+            typed: None,
+        }
+    }
+
+    /// Entries from oldest to newest, for saving
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Replace entries with provided entries from oldest to newest, for loading
+    pub fn load<I: IntoIterator<Item = String>>(&mut self, entries: I) {
+        self.entries.clear();
+        self.reset_recall();
+        for entry in entries {
+            self.push(entry);
+        }
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.reset_recall();
+    }
+
+    /// Maximum number of entries kept
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Set maximum number of entries kept, dropping the oldest entries if needed
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len;
+        self.truncate();
+    }
+
+    /// Add a new entry, moving it to the end if it already exists
+    pub fn push(&mut self, entry: String) {
+        self.reset_recall();
+        if entry.is_empty() {
+            return;
+        }
+        self.entries.retain(|other| other != &entry);
+        self.entries.push(entry);
+        self.truncate();
+    }
+
+    /// Stop recalling entries, the next recall will use a new prefix
+    pub fn reset_recall(&mut self) {
+        self.index = None;
+        self.typed = None;
+    }
+
+    /// Recall an older entry starting with the text typed before recall started.
+    /// Returns None if there is no such entry.
+    pub fn previous(&mut self, current: &str, filter: bool) -> Option<String> {
+        let typed = self.typed.get_or_insert_with(|| current.into());
+        let end = self.index.unwrap_or(self.entries.len());
+        let index = self
+            .entries
+            .get(..end)?
+            .iter()
+            .rposition(|entry| !filter || entry.starts_with(typed.as_str()))?;
+        self.index = Some(index);
+        self.entries.get(index).cloned()
+    }
+
+    /// Recall a newer entry starting with the text typed before recall started.
+    /// After the newest entry, the typed text is returned and recall stops.
+    /// Returns None if not recalling.
+    pub fn next(&mut self, filter: bool) -> Option<String> {
+        let start = self.index?.checked_add(1)?;
+        let typed = self.typed.as_deref().unwrap_or("");
+        let found = self
+            .entries
+            .get(start..)
+            .and_then(|entries| {
+                entries
+                    .iter()
+                    .position(|entry| !filter || entry.starts_with(typed))
+            })
+            .and_then(|position| start.checked_add(position));
+        match found {
+            Some(index) => {
+                self.index = Some(index);
+                self.entries.get(index).cloned()
+            }
+            None => {
+                let typed = self.typed.take().unwrap_or_default();
+                self.index = None;
+                Some(typed)
+            }
+        }
+    }
+
+    fn truncate(&mut self) {
+        if let Some(extra) = self.entries.len().checked_sub(self.max_len) {
+            self.entries.drain(..extra);
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        let mut history = History::new();
+        history.load(entries.iter().map(|entry| entry.to_string()));
+        history
+    }
+
+    #[test]
+    fn previous_filters_by_prefix() {
+        let mut history = history(&["set nu", "s/a/b/", "set list", "w"]);
+        assert_eq!(history.previous("se", true).as_deref(), Some("set list"));
+        // The prefix is the text typed before recall started, not the recalled entry
+        assert_eq!(
+            history.previous("set list", true).as_deref(),
+            Some("set nu")
+        );
+        assert_eq!(history.previous("set nu", true), None);
+        // Without filter every entry is recalled
+        history.reset_recall();
+        assert_eq!(history.previous("se", false).as_deref(), Some("w"));
+        assert_eq!(history.previous("w", false).as_deref(), Some("set list"));
+    }
+
+    #[test]
+    fn next_returns_typed_text() {
+        let mut history = history(&["set nu", "s/a/b/", "set list", "w"]);
+        assert_eq!(history.next(true), None);
+        assert_eq!(history.previous("se", true).as_deref(), Some("set list"));
+        assert_eq!(
+            history.previous("set list", true).as_deref(),
+            Some("set nu")
+        );
+        assert_eq!(history.next(true).as_deref(), Some("set list"));
+        assert_eq!(history.next(true).as_deref(), Some("se"));
+        // Recall stopped, so there is nothing newer
+        assert_eq!(history.next(true), None);
+    }
+
+    #[test]
+    fn push_moves_duplicates_and_truncates() {
+        let mut history = history(&["a", "b", "c"]);
+        history.push("a".to_string());
+        history.push(String::new());
+        assert_eq!(history.entries(), ["b", "c", "a"]);
+        history.set_max_len(2);
+        assert_eq!(history.entries(), ["c", "a"]);
+    }
+}
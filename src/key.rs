@@ -0,0 +1,191 @@
+use alloc::string::String;
+use core::ops::{BitOr, BitOrAssign};
+
+/// Key without modifiers
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum KeyCode {
+    Backspace,
+    Char(char),
+    Delete,
+    Down,
+    End,
+    Enter,
+    Escape,
+    /// Function key, from F1 to F24
+    F(u8),
+    Home,
+    Insert,
+    /// Key on the numeric keypad, by the character it types, like `5` or `+`, or `\n`
+    /// for the keypad Enter key
+    Keypad(char),
+    /// Center key on the numeric keypad when Num Lock is off
+    KeypadBegin,
+    Left,
+    Menu,
+    /// Mouse button or wheel
+    Mouse(Mouse),
+    PageDown,
+    PageUp,
+    Pause,
+    PrintScreen,
+    Right,
+    Tab,
+    Up,
+}
+
+/// Mouse button
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+/// What was done with the mouse
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MouseAction {
+    /// Button pressed, with the number of presses in quick succession, like 2 for a
+    /// double click
+    Press {
+        button: MouseButton,
+        clicks: u8,
+    },
+    /// Mouse moved while the button is held
+    Drag(MouseButton),
+    Release(MouseButton),
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+}
+
+/// Mouse action at a position in the buffer. Hosts find the position from the screen
+/// cell, so parsers do not need to know the layout.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Mouse {
+    pub action: MouseAction,
+    /// Line, starting at 0
+    pub line: usize,
+    /// Byte index in the line
+    pub index: usize,
+}
+
+/// Input method composition, where text like CJK characters is composed before it is
+/// committed to the buffer
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Composition {
+    /// Composition started, the composed text follows in updates
+    Start,
+    /// Composed text changed, with the input method's cursor as a byte index in the text
+    Update { text: String, cursor: Option<usize> },
+    /// Composition finished with the committed text, which is empty if it was cancelled
+    Commit(String),
+}
+
+/// Set of modifier keys held while a key was pressed
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Self = Self(0);
+    pub const SHIFT: Self = Self(1 << 0);
+    pub const CTRL: Self = Self(1 << 1);
+    /// Alt, which terminals and emacs call Meta
+    pub const ALT: Self = Self(1 << 2);
+    /// Same as [`Modifiers::ALT`]
+    pub const META: Self = Self::ALT;
+    /// Super, also called the Windows, Command, or Logo key
+    pub const SUPER: Self = Self(1 << 3);
+
+    /// Modifiers as bits
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Modifiers from bits, ignoring unknown bits
+    pub const fn from_bits_truncate(bits: u8) -> Self {
+        Self(bits & 0xF)
+    }
+
+    /// Returns true if no modifiers are held
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if all of the other modifiers are held
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Modifiers held in either set
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Modifiers held in this set but not in the other
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        *self = self.union(other);
+    }
+
+    pub fn remove(&mut self, other: Self) {
+        *self = self.difference(other);
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.union(other)
+    }
+}
+
+impl BitOrAssign for Modifiers {
+    fn bitor_assign(&mut self, other: Self) {
+        self.insert(other);
+    }
+}
+
+/// Key press, as a key code and the modifiers held with it
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+impl Key {
+    pub const fn new(code: KeyCode, modifiers: Modifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    /// Normalize so that Char('\n') is converted to Enter, for example. Shift is removed
+    /// from characters, as it is already part of the character.
+    pub fn normalize(self) -> Self {
+        let mut modifiers = self.modifiers;
+        let code = match self.code {
+            KeyCode::Char(c) | KeyCode::Keypad(c) => match c {
+                '\x08' => KeyCode::Backspace,
+                '\x7F' => KeyCode::Delete,
+                '\n' | '\r' => KeyCode::Enter,
+                '\x1B' => KeyCode::Escape,
+                '\t' => KeyCode::Tab,
+                _ => {
+                    modifiers.remove(Modifiers::SHIFT);
+                    KeyCode::Char(c)
+                }
+            },
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+}
+
+impl From<KeyCode> for Key {
+    fn from(code: KeyCode) -> Self {
+        Self::new(code, Modifiers::NONE)
+    }
+}
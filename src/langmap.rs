@@ -0,0 +1,94 @@
+use alloc::vec::Vec;
+
+/// Translation of characters typed with another keyboard layout to the characters of
+/// commands, like vim's `langmap`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Langmap {
+    pairs: Vec<(char, char)>,
+}
+
+impl Langmap {
+    pub fn new() -> Self {
+        Self { pairs: Vec::new() }
+    }
+
+    /// Translate a typed character, returning it unchanged if it is not mapped
+    pub fn get(&self, c: char) -> char {
+        self.pairs
+            .iter()
+            .find(|(from, _)| *from == c)
+            .map_or(c, |(_, to)| *to)
+    }
+
+    /// Map a typed character to another, replacing any existing mapping
+    pub fn insert(&mut self, from: char, to: char) {
+        self.pairs.retain(|(other, _)| *other != from);
+        self.pairs.push((from, to));
+    }
+
+    /// Returns true if no characters are mapped
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Define mappings using the value of vim's `langmap` option, with parts separated by
+    /// commas that are either pairs like `ΑA` or sequences like `абв;abc`. A backslash
+    /// escapes the next character. Returns false if the value is invalid, mappings before
+    /// the error are kept.
+    pub fn define(&mut self, value: &str) -> bool {
+        let mut chars = value.chars();
+        let mut from = Vec::new();
+        // Characters after the semicolon of a sequence
+        let mut to: Option<Vec<char>> = None;
+        loop {
+            let next = chars.next();
+            match next {
+                Some(',') | None => {
+                    if !self.define_part(&from, to.as_deref()) {
+                        return false;
+                    }
+                    if next.is_none() {
+                        return true;
+                    }
+                    from.clear();
+                    to = None;
+                }
+                Some(';') if to.is_none() => to = Some(Vec::new()),
+                Some(c) => {
+                    let c = if c == '\\' {
+                        match chars.next() {
+                            Some(c) => c,
+                            None => return false,
+                        }
+                    } else {
+                        c
+                    };
+                    match &mut to {
+                        Some(to) => to.push(c),
+                        None => from.push(c),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Define mappings for one part of a `langmap` value, returns false if it is invalid
+    fn define_part(&mut self, from: &[char], to: Option<&[char]>) -> bool {
+        match to {
+            Some(to) if to.len() == from.len() => {
+                for (from, to) in from.iter().zip(to) {
+                    self.insert(*from, *to);
+                }
+            }
+            None if from.chunks_exact(2).remainder().is_empty() => {
+                for pair in from.chunks_exact(2) {
+                    if let [from, to] = pair {
+                        self.insert(*from, *to);
+                    }
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
@@ -40,6 +40,48 @@ extern crate alloc;
 
 use alloc::string::String;
 
+pub use self::completion::*;
+mod completion;
+
+pub use self::cua::*;
+mod cua;
+
+pub use self::digraph::*;
+mod digraph;
+
+pub use self::emacs::*;
+mod emacs;
+
+pub use self::ex::*;
+mod ex;
+
+pub use self::fold::*;
+mod fold;
+
+pub use self::helix::*;
+mod helix;
+
+pub use self::history::*;
+mod history;
+
+pub use self::key::*;
+mod key;
+
+pub use self::langmap::*;
+mod langmap;
+
+pub use self::notation::*;
+mod notation;
+
+pub use self::regex::*;
+mod regex;
+
+pub use self::search::*;
+mod search;
+
+pub use self::terminal::*;
+mod terminal;
+
 pub use self::vi::*;
 mod vi;
 
@@ -51,89 +93,254 @@ pub enum Event {
     Backspace,
     /// Delete character before cursor without going beyond line boundaries
     BackspaceInLine,
+    /// Switch, list, or delete buffers
+    Buffer(BufferCommand),
     /// Finish grouping changes together
     ChangeFinish,
     /// Start grouping changes together
     ChangeStart,
+    /// Show the code of the character at cursor in decimal, hex, and octal
+    CharInfo,
+    /// Show the UTF-8 bytes of the character at cursor in hex
+    CharInfoUtf8,
+    /// Request completion candidates for the command line, which are provided with
+    /// [`ViParser::set_completion`]. Search is true if completing a search pattern.
+    Complete {
+        value: String,
+        cursor: usize,
+        search: bool,
+    },
     /// Delete character at cursor
     Delete,
     /// Delete character at cursor without going beyond line boundaries
     DeleteInLine,
+    /// Delete the text put by the last [`Event::PutAndMove`], moving the cursor to where
+    /// it was put, like emacs's `M-y` before putting an older kill
+    DeleteLastPut,
+    /// Edit a file, or reload the current file if path is None. Force discards changes.
+    Edit { path: Option<String>, force: bool },
     /// Escape key
     Escape,
+    /// Ex command not handled by the parser, like `:%d` or `:'<,'>s/a/b/`, for the editor
+    /// to run on the lines of the range resolved with [`ExAddress::resolve`]
+    Ex {
+        range: Option<ExRange>,
+        name: String,
+        bang: bool,
+        args: String,
+    },
+    /// Close the innermost open fold at cursor
+    FoldClose,
+    /// Close all folds
+    FoldCloseAll,
+    /// Create a closed fold from the lines of the selection
+    FoldCreate,
+    /// Delete the innermost fold at cursor
+    FoldDelete,
+    /// Delete all folds
+    FoldDeleteAll,
+    /// Open the closed fold at cursor
+    FoldOpen,
+    /// Open all folds
+    FoldOpenAll,
+    /// Open the fold at cursor if it is closed, otherwise close it
+    FoldToggle,
     /// Insert character at cursor
     Insert(char),
+    /// Insert pasted text at cursor and move cursor just after it, without auto-indent.
+    /// Line breaks are `\n`.
+    InsertText(String),
+    /// Join the line at cursor with the next line, inserting a space between them and
+    /// removing leading whitespace if spaces is true
+    Join { spaces: bool },
     /// Move cursor
     Motion(Motion),
     /// Create new line
     NewLine,
     /// Put from register
     Put { register: char, after: bool },
+    /// Put from register and move cursor just after the new text
+    PutAndMove { register: char, after: bool },
+    /// Show text being composed by an input method at cursor, without changing the
+    /// buffer, with the cursor as a byte index in the text. Empty text hides it.
+    Preedit { text: String, cursor: Option<usize> },
+    /// Put pasted text before cursor, like [`Event::Put`] from a register holding it.
+    /// Line breaks are `\n`.
+    PutText(String),
+    /// Quit, discarding changes if force is true
+    Quit { force: bool },
+    /// Redo last undone action
+    Redo,
     /// Notify of a mode change requiring redraw
     Redraw,
+    /// Move cursor left and undo the last [`Event::ReplaceChar`], [`Event::VirtualReplaceChar`],
+    /// or [`Event::NewLine`] in replace mode, restoring the replaced character or removing the appended one
+    ReplaceBackspace,
+    /// Replace character at cursor and move cursor right, or append it at end of line.
+    /// The replaced character should be remembered for [`Event::ReplaceBackspace`].
+    ReplaceChar(char),
+    /// Replace character at cursor without moving the cursor, or do nothing at end of line
+    ReplaceCharInLine(char),
+    /// Scroll the view so the line at cursor is at the provided position, moving the
+    /// cursor to the first non-blank character of the line if reset column is true
+    ScrollCursor {
+        position: ScrollPosition,
+        reset_column: bool,
+    },
+    /// Move the view the provided number of lines down, keeping the cursor in the view
+    ScrollDown(usize),
+    /// Move the view the provided number of columns to the left, when lines are not wrapped
+    ScrollLeft(usize),
+    /// Move the view the provided number of columns to the right, when lines are not wrapped
+    ScrollRight(usize),
+    /// Move the view the provided number of lines up, keeping the cursor in the view
+    ScrollUp(usize),
+    /// Preview search while it is typed, highlighting matches and moving the view to
+    /// the next match from the cursor position when the search started
+    SearchPreview(SearchRequest),
+    /// Stop previewing search, restoring the cursor and view from when the search started
+    SearchPreviewCancel,
     /// Clear selection
     SelectClear,
     /// Start selection
     SelectStart,
     /// Start selection by line
     SelectLineStart,
+    /// Select the same text as the last selection that was cleared
+    SelectLast,
     /// Select text object
     SelectTextObject(TextObject, bool),
-    /// Set search
-    SetSearch(String, bool),
+    /// Add, remove, or change multiple selections
+    Selection(SelectionCommand),
+    /// Set search, the cursor is moved with a following [`Motion::NextSearch`]
+    SetSearch(SearchRequest),
     /// Shift text to the left
     ShiftLeft,
     /// Shift text to the right
     ShiftRight,
+    /// Repeat the last substitute with the same pattern and flags on all lines
+    SubstituteRepeatAll,
     /// Swap case
     SwapCase,
+    /// Open, close, or switch tab pages
+    TabPage(TabPageCommand),
     /// Undo last action
     Undo,
+    /// Replace screen cells at cursor with character and move cursor right, like
+    /// [`Event::ReplaceChar`]. If the character at cursor is a Tab spanning more cells,
+    /// insert before it instead so the following text stays in place.
+    VirtualReplaceChar(char),
+    /// Split, close, focus, or resize windows
+    Window(WindowCommand),
+    /// Write to a file
+    Write(WriteRequest),
+    /// Write to a file and quit
+    WriteQuit(WriteRequest),
     /// Yank to register
     Yank { register: char },
+    /// Yank to register, appending to the text it holds, like an uppercase register in vi
+    YankAppend { register: char },
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum Key {
-    //TODO: Ctrl keys?
-    Backspace,
-    Backtab,
-    Char(char),
-    Ctrl(char),
-    Delete,
-    Down,
-    End,
-    Enter,
-    Escape,
-    Home,
-    Left,
-    PageDown,
-    PageUp,
-    Right,
-    Tab,
-    Up,
+/// Commands for editors with multiple selections, where one selection is the primary one
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SelectionCommand {
+    /// Add a selection on the next line, with the same columns as the primary selection
+    AddBelow,
+    /// Add a selection on the previous line, with the same columns as the primary selection
+    AddAbove,
+    /// Remove all selections except the primary one
+    KeepPrimary,
+    /// Remove the primary selection, making the next one primary
+    RemovePrimary,
+    /// Make the next or previous selection primary
+    Rotate { forwards: bool },
+    /// Swap the cursor and anchor of each selection
+    Flip,
+    /// Swap the cursor and anchor of each selection where the cursor is before the anchor,
+    /// so every cursor is at the end of its selection
+    Forward,
+    /// Replace selections with the matches of the pattern inside them
+    Select(String),
+    /// Split selections at the matches of the pattern
+    Split(String),
+    /// Split selections into one selection per line
+    SplitLines,
+    /// Keep only selections that match the pattern, or that do not match if matching is false
+    Keep { pattern: String, matching: bool },
+    /// Replace each selected character with the character
+    ReplaceChars(char),
 }
 
-impl Key {
-    /// Normalize so that Char('\n') is converted to Enter, for example
-    pub fn normalize(self) -> Self {
-        match self {
-            Key::Char(c) => match c {
-                '\x08' => Key::Backspace,
-                '\x7F' => Key::Delete,
-                '\n' | '\r' => Key::Enter,
-                '\x1B' => Key::Escape,
-                '\t' => Key::Tab,
-                _ => Key::Char(c),
-            },
-            key => key,
-        }
-    }
+/// Position in text, as a line number and byte index into the line
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct TextPos {
+    pub line: usize,
+    pub index: usize,
 }
 
 pub trait Parser {
     fn reset(&mut self);
     fn parse<F: FnMut(Event)>(&mut self, key: Key, selection: bool, callback: F);
+
+    /// Parse pasted text, like from a terminal's bracketed paste. Parsers should insert
+    /// it as one change instead of running it as commands. By default, each character is
+    /// parsed as a typed key.
+    fn paste<F: FnMut(Event)>(&mut self, text: &str, selection: bool, mut callback: F) {
+        for c in text.chars() {
+            self.parse(Key::from(KeyCode::Char(c)), selection, &mut callback);
+        }
+    }
+
+    /// Parse input method composition. By default, composed text is shown with
+    /// [`Event::Preedit`] and committed text is parsed as typed characters.
+    fn compose<F: FnMut(Event)>(
+        &mut self,
+        composition: Composition,
+        selection: bool,
+        mut callback: F,
+    ) {
+        match composition {
+            Composition::Start => {}
+            Composition::Update { text, cursor } => {
+                callback(Event::Preedit { text, cursor });
+                callback(Event::Redraw);
+            }
+            Composition::Commit(text) => {
+                callback(Event::Preedit {
+                    text: String::new(),
+                    cursor: None,
+                });
+                if text.is_empty() {
+                    callback(Event::Redraw);
+                }
+                for c in text.chars() {
+                    self.parse(Key::from(KeyCode::Char(c)), selection, &mut callback);
+                }
+            }
+        }
+    }
+
+    /// Parse keys in vim key notation, like `ciw<C-r>"<Esc>`, starting with the provided
+    /// selection state and updating it from the select events of each key
+    fn parse_keys<F: FnMut(Event)>(&mut self, keys: &str, mut selection: bool, mut callback: F) {
+        let KeySequence(keys) = KeySequence::parse_with_leader(keys, KEY_DEFAULT_LEADER);
+        for key in keys {
+            let mut next = selection;
+            self.parse(key, selection, |event| {
+                match event {
+                    Event::SelectStart
+                    | Event::SelectLineStart
+                    | Event::SelectLast
+                    | Event::SelectTextObject(..) => next = true,
+                    Event::SelectClear => next = false,
+                    _ => {}
+                }
+                callback(event);
+            });
+            selection = next;
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -141,12 +348,133 @@ pub enum Operator {
     AutoIndent,
     Change,
     Delete,
+    Fold,
     ShiftLeft,
     ShiftRight,
     SwapCase,
     Yank,
 }
 
+/// Where to place the line at cursor when scrolling the view
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScrollPosition {
+    Top,
+    Center,
+    Bottom,
+}
+
+/// Buffer command from an ex command or Ctrl-^
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BufferCommand {
+    /// Go forward the provided number of buffers, wrapping around
+    Next(usize),
+    /// Go back the provided number of buffers, wrapping around
+    Previous(usize),
+    First,
+    Last,
+    /// Buffer by number, starting at 1
+    Number(usize),
+    /// Buffer with a name matching the provided name
+    Name(String),
+    /// Alternate buffer, usually the previously edited one
+    Alternate,
+    /// List buffers, including unlisted buffers if all is true
+    List {
+        all: bool,
+    },
+    /// Delete the buffer by number, or the current buffer if None. Force discards changes.
+    Delete {
+        number: Option<usize>,
+        force: bool,
+    },
+}
+
+/// Tab page command from an ex command or `gt` and `gT`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TabPageCommand {
+    /// Go to the next tab page, wrapping around
+    Next,
+    /// Go back the provided number of tab pages, wrapping around
+    Previous(usize),
+    First,
+    Last,
+    /// Tab page by number, starting at 1
+    Number(usize),
+    /// Open a new tab page after the current one, editing a file if provided
+    New {
+        path: Option<String>,
+    },
+    /// Close the tab page, hiding changed buffers only when force is true
+    Close {
+        force: bool,
+    },
+    /// Close all other tab pages, hiding changed buffers only when force is true
+    Only {
+        force: bool,
+    },
+}
+
+/// Window to focus, counts are the number of windows to move
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WindowFocus {
+    Left(usize),
+    Down(usize),
+    Up(usize),
+    Right(usize),
+    /// Next window, wrapping around
+    Next,
+    /// Previous window, wrapping around
+    Previous,
+    /// Window by number, starting at 1
+    Number(usize),
+    /// Previously focused window
+    Last,
+    Top,
+    Bottom,
+}
+
+/// Change of window size, relative sizes are in lines or columns
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WindowResize {
+    /// Add to height, negative to reduce it
+    Height(isize),
+    /// Add to width, negative to reduce it
+    Width(isize),
+    /// Set height, or use as much height as possible if None
+    SetHeight(Option<usize>),
+    /// Set width, or use as much width as possible if None
+    SetWidth(Option<usize>),
+    /// Make all windows almost the same size
+    Equal,
+}
+
+/// Window command from Ctrl-W or an ex command
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WindowCommand {
+    /// Split the window horizontally, with the new window height and file to edit if provided
+    Split {
+        size: Option<usize>,
+        path: Option<String>,
+    },
+    /// Split the window vertically, with the new window width and file to edit if provided
+    VerticalSplit {
+        size: Option<usize>,
+        path: Option<String>,
+    },
+    /// Close the window, hiding the buffer if it was changed only when force is true
+    Close { force: bool },
+    /// Close all other windows, hiding changed buffers only when force is true
+    Only { force: bool },
+    /// Focus another window
+    Focus(WindowFocus),
+    /// Resize the window
+    Resize(WindowResize),
+    /// Rotate windows in the row or column the provided number of times
+    Rotate { downwards: bool, count: usize },
+    /// Exchange the window with the next one, or with the window by number if provided
+    Exchange(Option<usize>),
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Word {
     Lower,
@@ -160,6 +488,32 @@ pub enum WordChar {
     NonBlank,
 }
 
+impl WordChar {
+    /// Classify a character according to word type
+    pub fn new(c: char, word: Word) -> Self {
+        match word {
+            Word::Lower => {
+                // A "word" is either a group of letters, digits, and underscores,
+                // or a sequence of other non-blank characters
+                if c.is_whitespace() {
+                    Self::Blank
+                } else if c.is_alphanumeric() || c == '_' {
+                    Self::Keyword
+                } else {
+                    Self::NonBlank
+                }
+            }
+            Word::Upper => {
+                if c.is_whitespace() {
+                    Self::Blank
+                } else {
+                    Self::NonBlank
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct WordIter<'a> {
     line: &'a str,
@@ -187,26 +541,7 @@ impl<'a> Iterator for WordIter<'a> {
         for (sub_index, c) in self.line.get(self.index..)?.char_indices() {
             let index = self.index.checked_add(sub_index)?;
 
-            let kind = match self.word {
-                Word::Lower => {
-                    // A "word" is either a group of letters, digits, and underscores,
-                    // or a sequence of other non-blank characters
-                    if c.is_whitespace() {
-                        WordChar::Blank
-                    } else if c.is_alphanumeric() || c == '_' {
-                        WordChar::Keyword
-                    } else {
-                        WordChar::NonBlank
-                    }
-                }
-                Word::Upper => {
-                    if c.is_whitespace() {
-                        WordChar::Blank
-                    } else {
-                        WordChar::NonBlank
-                    }
-                }
-            };
+            let kind = WordChar::new(c, self.word);
 
             if kind != last_kind {
                 // Word either starts or ends
@@ -245,31 +580,54 @@ pub enum Motion {
     Around,
     Down,
     End,
+    /// End of line, or start of the next line if the cursor is at end of line
+    EndOrNextLine,
+    /// End of the fold at cursor
+    FoldEnd,
+    /// Start of the fold at cursor
+    FoldStart,
     GotoEof,
+    /// Byte offset in the buffer, starting at 1
+    GotoByte(usize),
     GotoLine(usize),
     Home,
     Inside,
+    /// Position where insert mode was last stopped
+    LastInsert,
     Left,
     LeftInLine,
     Line,
     NextChar(char),
     NextCharTill(char),
+    /// Start of the next fold
+    NextFoldStart,
     NextSearch,
     NextWordEnd(Word),
     NextWordStart(Word),
     PageDown,
     PageUp,
+    /// Line and byte index in the line, both starting at 0, like from a mouse click
+    Position {
+        line: usize,
+        index: usize,
+    },
     PreviousChar(char),
     PreviousCharTill(char),
+    /// End of the previous fold
+    PreviousFoldEnd,
     PreviousSearch,
     PreviousWordEnd(Word),
     PreviousWordStart(Word),
     Right,
     RightInLine,
     ScreenHigh,
+    /// Half a screen width to the right of the start of the screen line
+    ScreenLineMiddle,
     ScreenLow,
     ScreenMiddle,
     Selection,
+    /// Last non-blank character of the line, after moving down the provided number of lines
+    SoftEnd(usize),
     SoftHome,
     Up,
 }
@@ -281,31 +639,41 @@ impl Motion {
             Self::Around => None,
             Self::Down => Some(Self::Up),
             Self::End => Some(Self::Home),
+            Self::EndOrNextLine => None,
+            Self::FoldEnd => Some(Self::FoldStart),
+            Self::FoldStart => Some(Self::FoldEnd),
             Self::GotoEof => None,
+            Self::GotoByte(_byte) => None,
             Self::GotoLine(_line) => None,
             Self::Home => Some(Self::End),
             Self::Inside => None,
+            Self::LastInsert => None,
             Self::Left => Some(Self::Right),
             Self::LeftInLine => Some(Self::RightInLine),
             Self::Line => None,
             Self::NextChar(c) => Some(Self::PreviousChar(c)),
             Self::NextCharTill(c) => Some(Self::PreviousCharTill(c)),
+            Self::NextFoldStart => None,
             Self::NextSearch => Some(Self::PreviousSearch),
             Self::NextWordEnd(word) => Some(Self::PreviousWordEnd(word)),
             Self::NextWordStart(word) => Some(Self::PreviousWordStart(word)),
             Self::PageDown => Some(Self::PageUp),
             Self::PageUp => Some(Self::PageDown),
+            Self::Position { .. } => None,
             Self::PreviousChar(c) => Some(Self::NextChar(c)),
             Self::PreviousCharTill(c) => Some(Self::NextCharTill(c)),
+            Self::PreviousFoldEnd => None,
             Self::PreviousSearch => Some(Self::NextSearch),
             Self::PreviousWordEnd(word) => Some(Self::NextWordEnd(word)),
             Self::PreviousWordStart(word) => Some(Self::NextWordStart(word)),
             Self::Right => Some(Self::Left),
             Self::RightInLine => Some(Self::LeftInLine),
             Self::ScreenHigh => None,
+            Self::ScreenLineMiddle => None,
             Self::ScreenLow => None,
             Self::ScreenMiddle => None,
             Self::Selection => None,
+            Self::SoftEnd(_lines) => Some(Self::SoftHome),
             Self::SoftHome => Some(Self::End),
             Self::Up => Some(Self::Down),
         }
@@ -313,10 +681,7 @@ impl Motion {
 
     /// Returns true if text object is needed
     pub fn text_object(&self) -> bool {
-        match self {
-            Self::Around | Self::Inside => true,
-            _ => false,
-        }
+        matches!(self, Self::Around | Self::Inside)
     }
 }
 
@@ -336,3 +701,59 @@ pub enum TextObject {
     Ticks,
     Word(Word),
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn parse_keys<P: Parser>(parser: &mut P, keys: &str) -> Vec<Event> {
+        let mut events = Vec::new();
+        parser.parse_keys(keys, false, |event| events.push(event));
+        events
+    }
+
+    /// Parser that only reports typed characters
+    struct CharParser;
+
+    impl Parser for CharParser {
+        fn reset(&mut self) {}
+
+        fn parse<F: FnMut(Event)>(&mut self, key: Key, _selection: bool, mut callback: F) {
+            if let KeyCode::Char(c) = key.code {
+                callback(Event::Insert(c));
+            }
+        }
+    }
+
+    #[test]
+    fn paste_default() {
+        let mut events = Vec::new();
+        CharParser.paste("a\nb", false, |event| events.push(event));
+        let chars = events
+            .iter()
+            .map(|event| match event {
+                Event::Insert(c) => *c,
+                _ => '?',
+            })
+            .collect::<String>();
+        assert_eq!(chars, "a\nb");
+    }
+
+    #[test]
+    fn parse_keys_selection() {
+        // The selection started by v is passed to d
+        let events = parse_keys(&mut ViParser::new(), "vjd");
+        assert!(events.iter().any(|event| matches!(event, Event::Delete)));
+
+        // The selection started by w is kept by d
+        let events = parse_keys(&mut HelixParser::new(), "wd");
+        let starts = events
+            .iter()
+            .filter(|event| matches!(event, Event::SelectStart))
+            .count();
+        assert_eq!(starts, 1);
+        assert!(events.iter().any(|event| matches!(event, Event::Delete)));
+    }
+}
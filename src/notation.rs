@@ -0,0 +1,338 @@
+//! Vim key notation, like `ciw<C-r>"<Esc>`
+//!
+//! Special keys are written between `<` and `>`, with modifiers before the name: `S-` for
+//! Shift, `C-` for Ctrl, `M-` or `A-` for Alt, and `D-` for Super. Names are not case
+//! sensitive. A `<` that does not start a valid key is the `<` character, like in vim.
+
+use alloc::vec::Vec;
+use core::{convert::Infallible, fmt, str::FromStr};
+
+use crate::{Key, KeyCode, Modifiers, MouseAction, MouseButton};
+
+/// Key used for `<Leader>` if no other leader is provided, like vim's default `mapleader`
+pub const KEY_DEFAULT_LEADER: Key = Key::new(KeyCode::Char('\\'), Modifiers::NONE);
+
+/// Names of special keys, the first name of each key is used when printing
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("BS", KeyCode::Backspace),
+    ("Backspace", KeyCode::Backspace),
+    ("Del", KeyCode::Delete),
+    ("Delete", KeyCode::Delete),
+    ("Down", KeyCode::Down),
+    ("End", KeyCode::End),
+    ("CR", KeyCode::Enter),
+    ("Return", KeyCode::Enter),
+    ("Enter", KeyCode::Enter),
+    ("NL", KeyCode::Enter),
+    ("Esc", KeyCode::Escape),
+    ("Home", KeyCode::Home),
+    ("Insert", KeyCode::Insert),
+    ("Ins", KeyCode::Insert),
+    ("kPlus", KeyCode::Keypad('+')),
+    ("kMinus", KeyCode::Keypad('-')),
+    ("kMultiply", KeyCode::Keypad('*')),
+    ("kDivide", KeyCode::Keypad('/')),
+    ("kPoint", KeyCode::Keypad('.')),
+    ("kComma", KeyCode::Keypad(',')),
+    ("kEqual", KeyCode::Keypad('=')),
+    ("kEnter", KeyCode::Keypad('\n')),
+    ("kOrigin", KeyCode::KeypadBegin),
+    ("Left", KeyCode::Left),
+    ("Menu", KeyCode::Menu),
+    ("PageDown", KeyCode::PageDown),
+    ("PageUp", KeyCode::PageUp),
+    ("Pause", KeyCode::Pause),
+    ("PrintScreen", KeyCode::PrintScreen),
+    ("Print", KeyCode::PrintScreen),
+    ("Right", KeyCode::Right),
+    ("Tab", KeyCode::Tab),
+    ("Up", KeyCode::Up),
+];
+
+/// Names of characters that cannot be written directly
+const CHAR_NAMES: &[(&str, char)] = &[
+    ("lt", '<'),
+    ("Space", ' '),
+    ("Bar", '|'),
+    ("Bslash", '\\'),
+    ("Nul", '\0'),
+];
+
+/// Error parsing a single key
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyParseError {
+    /// No key was provided
+    Empty,
+    /// More than one key was provided
+    Multiple,
+}
+
+impl fmt::Display for KeyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "no key"),
+            Self::Multiple => write!(f, "more than one key"),
+        }
+    }
+}
+
+/// Parse the inside of `<...>`, like `C-w`, returns None if it is not a key
+fn parse_special(value: &str) -> Option<Key> {
+    let mut modifiers = Modifiers::NONE;
+    let mut rest = value;
+    loop {
+        let mut chars = rest.chars();
+        let modifier = match chars.next()?.to_ascii_uppercase() {
+            'S' => Modifiers::SHIFT,
+            'C' => Modifiers::CTRL,
+            'A' | 'M' | 'T' => Modifiers::ALT,
+            'D' => Modifiers::SUPER,
+            _ => break,
+        };
+        // The modifier must be followed by a dash and the key
+        if chars.next() != Some('-') || chars.as_str().is_empty() {
+            break;
+        }
+        modifiers.insert(modifier);
+        rest = chars.as_str();
+    }
+
+    let mut chars = rest.chars();
+    let code = match (chars.next(), chars.next()) {
+        // Like vim, a character needs a modifier, so `<x>` is not a key
+        (Some(c), None) if !modifiers.is_empty() => KeyCode::Char(c),
+        _ => {
+            if let Some((_, c)) = CHAR_NAMES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(rest))
+            {
+                KeyCode::Char(*c)
+            } else if let Some((_, code)) = KEY_NAMES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(rest))
+            {
+                *code
+            } else if let Some(digit) = rest
+                .strip_prefix(['k', 'K'])
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| digit.chars().next())
+                .filter(char::is_ascii_digit)
+            {
+                KeyCode::Keypad(digit)
+            } else {
+                let n = rest.strip_prefix(['f', 'F'])?.parse().ok()?;
+                if !(1..=24).contains(&n) {
+                    return None;
+                }
+                KeyCode::F(n)
+            }
+        }
+    };
+
+    // Like vim, control characters are not case sensitive
+    let code = match code {
+        KeyCode::Char(c) if modifiers.contains(Modifiers::CTRL) => {
+            KeyCode::Char(c.to_ascii_lowercase())
+        }
+        code => code,
+    };
+    Some(Key::new(code, modifiers))
+}
+
+/// Sequence of keys in vim key notation, like `ciw<C-r>"<Esc>`
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct KeySequence(pub Vec<Key>);
+
+impl KeySequence {
+    /// Parse keys, replacing `<Leader>` with the provided key
+    pub fn parse_with_leader(value: &str, leader: Key) -> Self {
+        let mut keys = Vec::new();
+        let mut rest = value;
+        while let Some(c) = rest.chars().next() {
+            rest = rest.get(c.len_utf8()..).unwrap_or("");
+            if c == '<' {
+                if let Some(end) = rest.find('>') {
+                    // The key after a modifier can also be `>`, like `<C->>`
+                    let ends = [
+                        Some(end),
+                        Some(end.saturating_add(1))
+                            .filter(|&end| rest.get(end..).is_some_and(|x| x.starts_with('>'))),
+                    ];
+                    let special = ends.into_iter().flatten().find_map(|end| {
+                        let name = rest.get(..end).unwrap_or("");
+                        // Names cannot contain <, except for the character after a modifier
+                        let key = if name.eq_ignore_ascii_case("leader") {
+                            Some(leader)
+                        } else if name.contains('<') && !name.ends_with("-<") {
+                            None
+                        } else {
+                            parse_special(name)
+                        };
+                        key.map(|key| (key, end))
+                    });
+                    if let Some((key, end)) = special {
+                        keys.push(key);
+                        rest = rest.get(end.saturating_add(1)..).unwrap_or("");
+                        continue;
+                    }
+                }
+            }
+            keys.push(Key::from(KeyCode::Char(c)));
+        }
+        Self(keys)
+    }
+}
+
+impl FromStr for KeySequence {
+    type Err = Infallible;
+
+    /// Parse keys, using [`KEY_DEFAULT_LEADER`] for `<Leader>`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(Self::parse_with_leader(value, KEY_DEFAULT_LEADER))
+    }
+}
+
+impl fmt::Display for KeySequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for key in self.0.iter() {
+            write!(f, "{key}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Key {
+    type Err = KeyParseError;
+
+    /// Parse one key, like `x` or `<C-w>`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let KeySequence(keys) = KeySequence::parse_with_leader(value, KEY_DEFAULT_LEADER);
+        match keys.as_slice() {
+            [] => Err(KeyParseError::Empty),
+            [key] => Ok(*key),
+            _ => Err(KeyParseError::Multiple),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Show control characters like Char('\n') with their names
+        let key = match self.code {
+            KeyCode::Char(c) if c.is_control() => self.normalize(),
+            _ => *self,
+        };
+        let mut modifiers = key.modifiers;
+        let code = match key.code {
+            // Show control characters as Ctrl with the character
+            KeyCode::Char(c @ '\x01'..='\x1A') => {
+                modifiers.insert(Modifiers::CTRL);
+                let c = u8::try_from(c).map_or(c, |c| char::from(c | 0x60));
+                KeyCode::Char(c)
+            }
+            code => code,
+        };
+
+        if modifiers.is_empty() {
+            if let KeyCode::Char(c) = code {
+                if c != '<' && c != ' ' && c != '\0' {
+                    return write!(f, "{c}");
+                }
+            }
+        }
+
+        write!(f, "<")?;
+        for (modifier, prefix) in [
+            (Modifiers::ALT, "M-"),
+            (Modifiers::CTRL, "C-"),
+            (Modifiers::SHIFT, "S-"),
+            (Modifiers::SUPER, "D-"),
+        ] {
+            if modifiers.contains(modifier) {
+                write!(f, "{prefix}")?;
+            }
+        }
+        match code {
+            KeyCode::Char(c) => match CHAR_NAMES.iter().find(|(_, named)| *named == c) {
+                Some((name, _)) => write!(f, "{name}")?,
+                None => write!(f, "{c}")?,
+            },
+            KeyCode::F(n) => write!(f, "F{n}")?,
+            KeyCode::Keypad(c @ '0'..='9') => write!(f, "k{c}")?,
+            // Mouse keys are printed like vim, without their position
+            KeyCode::Mouse(mouse) => {
+                let (button, name) = match mouse.action {
+                    MouseAction::Press { button, clicks } => {
+                        if clicks > 1 {
+                            write!(f, "{clicks}-")?;
+                        }
+                        (Some(button), "Mouse")
+                    }
+                    MouseAction::Drag(button) => (Some(button), "Drag"),
+                    MouseAction::Release(button) => (Some(button), "Release"),
+                    MouseAction::ScrollUp => (None, "ScrollWheelUp"),
+                    MouseAction::ScrollDown => (None, "ScrollWheelDown"),
+                    MouseAction::ScrollLeft => (None, "ScrollWheelLeft"),
+                    MouseAction::ScrollRight => (None, "ScrollWheelRight"),
+                };
+                match button {
+                    Some(MouseButton::Left) => write!(f, "Left")?,
+                    Some(MouseButton::Middle) => write!(f, "Middle")?,
+                    Some(MouseButton::Right) => write!(f, "Right")?,
+                    None => {}
+                }
+                write!(f, "{name}")?;
+            }
+            code => match KEY_NAMES.iter().find(|(_, named)| *named == code) {
+                Some((name, _)) => write!(f, "{name}")?,
+                None => write!(f, "{code:?}")?,
+            },
+        }
+        write!(f, ">")
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use alloc::{string::ToString, vec};
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for notation in [
+            "<C->>",
+            "<M->>x",
+            "<C-->",
+            "<C-->>",
+            "<Nul>",
+            "a<lt>b<Space>",
+            "<C-w>",
+        ] {
+            let keys = notation.parse::<KeySequence>().unwrap();
+            assert_eq!(keys.to_string(), notation);
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let key = |code, modifiers| Key::new(code, modifiers);
+        assert_eq!(
+            "<C->>".parse::<KeySequence>().unwrap().0,
+            vec![key(KeyCode::Char('>'), Modifiers::CTRL)]
+        );
+        assert_eq!(
+            "<C-->>".parse::<KeySequence>().unwrap().0,
+            vec![
+                key(KeyCode::Char('-'), Modifiers::CTRL),
+                key(KeyCode::Char('>'), Modifiers::NONE)
+            ]
+        );
+        assert_eq!(
+            "<Nul>".parse::<Key>(),
+            Ok(key(KeyCode::Char('\0'), Modifiers::NONE))
+        );
+        assert_eq!("<->>".parse::<KeySequence>().unwrap().0.len(), 4);
+    }
+}
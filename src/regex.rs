@@ -0,0 +1,1679 @@
+//! Vim flavoured regular expressions, matched over lines of text
+//!
+//! Supports the magic (`\m`), very magic (`\v`), nomagic (`\M`) and very nomagic (`\V`)
+//! modes, case flags (`\c`, `\C`), multiline atoms (`\n`, `\_s`, `\_.`, `\_[]`), word
+//! boundaries (`\<`, `\>`), bounded repeats (`\{n,m}`, `\{-n,m}`), groups, back
+//! references, lookaround (`\@=`, `\@!`, `\@<=`, `\@<!`, `\@>`), and match bounds
+//! (`\zs`, `\ze`).
+
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::{fmt, slice, str::Chars};
+
+use crate::TextPos;
+
+/// Number of steps a search can use besides [`REGEX_START_STEPS`], to limit catastrophic
+/// backtracking
+pub const REGEX_MAX_STEPS: usize = 1_000_000;
+
+/// Number of steps added to the budget of a search for each start position tried, so
+/// searching a long buffer for a simple pattern does not give up
+pub const REGEX_START_STEPS: usize = 100;
+
+/// Maximum nesting of the matcher, a search that goes deeper gives up instead of
+/// overflowing the stack
+pub const REGEX_MAX_DEPTH: usize = 200;
+
+/// Maximum number of repetitions of a group that can be backtracked into, a search with
+/// more gives up
+pub const REGEX_MAX_REPEATS: usize = 100_000;
+
+/// Number of groups in a [`RegexMatch`], group 0 is the whole match
+pub const REGEX_GROUPS: usize = 10;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RegexError {
+    /// Backslash at end of pattern
+    TrailingBackslash,
+    /// Group was not closed
+    UnmatchedOpenParen,
+    /// Group was closed without being opened
+    UnmatchedCloseParen,
+    /// More than nine capturing groups
+    TooManyGroups,
+    /// Invalid `\{n,m}` repeat
+    InvalidBrace,
+    /// Invalid `\@` lookaround
+    InvalidLookaround,
+    /// Invalid `\%` item
+    InvalidPercent,
+    /// Invalid `\z` item
+    InvalidZ,
+    /// Back reference to a group that is not defined before it
+    InvalidBackref(usize),
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TrailingBackslash => write!(f, "trailing backslash"),
+            Self::UnmatchedOpenParen => write!(f, "unmatched \\("),
+            Self::UnmatchedCloseParen => write!(f, "unmatched \\)"),
+            Self::TooManyGroups => write!(f, "too many \\("),
+            Self::InvalidBrace => write!(f, "syntax error in \\{{...}}"),
+            Self::InvalidLookaround => write!(f, "invalid character after \\@"),
+            Self::InvalidPercent => write!(f, "invalid character after \\%"),
+            Self::InvalidZ => write!(f, "invalid character after \\z"),
+            Self::InvalidBackref(group) => write!(f, "illegal back reference \\{group}"),
+        }
+    }
+}
+
+/// Error returned when a search gives up before finding out whether there is a match,
+/// because it used up its step budget or reached [`REGEX_MAX_DEPTH`] or
+/// [`REGEX_MAX_REPEATS`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RegexExhausted;
+
+impl fmt::Display for RegexExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pattern is too complex")
+    }
+}
+
+/// Options used when compiling a [`Regex`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RegexOptions {
+    /// Ignore case, like vim's `ignorecase`
+    pub ignore_case: bool,
+    /// Match case if the pattern has upper case characters, like vim's `smartcase`
+    pub smart_case: bool,
+    /// Start in nomagic mode (`\M`), like vim's `nomagic`
+    pub nomagic: bool,
+    /// Last substitute string, used by `~`
+    pub last_substitute: Option<String>,
+}
+
+/// A match of a [`Regex`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RegexMatch {
+    /// Start of match, changed by `\zs`
+    pub start: TextPos,
+    /// End of match (exclusive), changed by `\ze`
+    pub end: TextPos,
+    /// Start and end of groups, group 0 is the whole match
+    pub groups: [Option<(TextPos, TextPos)>; REGEX_GROUPS],
+}
+
+impl RegexMatch {
+    /// Start and end of group, group 0 is the whole match
+    pub fn group(&self, group: usize) -> Option<(TextPos, TextPos)> {
+        self.groups.get(group).copied().flatten()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Builtin {
+    Alnum,
+    Alpha,
+    Backspace,
+    Blank,
+    Cntrl,
+    Digit,
+    Escape,
+    Fname,
+    Graph,
+    Head,
+    Hex,
+    Ident,
+    Keyword,
+    Lower,
+    Octal,
+    Print,
+    Punct,
+    Return,
+    Tab,
+    Upper,
+    Whitespace,
+    Word,
+}
+
+impl Builtin {
+    /// Builtin for character class escape like `\s`, and if it is negated
+    fn from_escape(c: char) -> Option<(Self, bool)> {
+        let builtin = match c.to_ascii_lowercase() {
+            'a' => Self::Alpha,
+            'd' => Self::Digit,
+            'f' => Self::Fname,
+            'h' => Self::Head,
+            'i' => Self::Ident,
+            'k' => Self::Keyword,
+            'l' => Self::Lower,
+            'o' => Self::Octal,
+            'p' => Self::Print,
+            's' => Self::Blank,
+            'u' => Self::Upper,
+            'w' => Self::Word,
+            'x' => Self::Hex,
+            _ => return None,
+        };
+        // Upper case negates the class
+        Some((builtin, c.is_ascii_uppercase()))
+    }
+
+    /// Builtin for a named class like `[:alpha:]`
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "alnum" => Self::Alnum,
+            "alpha" => Self::Alpha,
+            "backspace" => Self::Backspace,
+            "blank" => Self::Blank,
+            "cntrl" => Self::Cntrl,
+            "digit" => Self::Digit,
+            "escape" => Self::Escape,
+            "fname" => Self::Fname,
+            "graph" => Self::Graph,
+            "ident" => Self::Ident,
+            "keyword" => Self::Keyword,
+            "lower" => Self::Lower,
+            "print" => Self::Print,
+            "punct" => Self::Punct,
+            "return" => Self::Return,
+            "space" => Self::Whitespace,
+            "tab" => Self::Tab,
+            "upper" => Self::Upper,
+            "xdigit" => Self::Hex,
+            _ => return None,
+        })
+    }
+
+    fn matches(self, c: char) -> bool {
+        match self {
+            Self::Alnum => c.is_alphanumeric(),
+            Self::Alpha => c.is_ascii_alphabetic(),
+            Self::Backspace => c == '\x08',
+            Self::Blank => c == ' ' || c == '\t',
+            Self::Cntrl => c.is_control(),
+            Self::Digit => c.is_ascii_digit(),
+            Self::Escape => c == '\x1B',
+            Self::Fname => c.is_alphanumeric() || "/.-_+,#$%~=".contains(c),
+            Self::Graph => !c.is_control() && !c.is_whitespace(),
+            Self::Head => c.is_ascii_alphabetic() || c == '_',
+            Self::Hex => c.is_ascii_hexdigit(),
+            Self::Ident | Self::Keyword => c.is_alphanumeric() || c == '_',
+            Self::Lower => c.is_lowercase(),
+            Self::Octal => c.is_digit(8),
+            Self::Print => !c.is_control(),
+            Self::Punct => c.is_ascii_punctuation(),
+            Self::Return => c == '\r',
+            Self::Tab => c == '\t',
+            Self::Upper => c.is_uppercase(),
+            Self::Whitespace => c.is_whitespace(),
+            Self::Word => c.is_ascii_alphanumeric() || c == '_',
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum ClassItem {
+    Builtin(Builtin),
+    Char(char),
+    Range(char, char),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+    /// Also matches end of line
+    newline: bool,
+}
+
+impl Class {
+    fn builtin(builtin: Builtin, negated: bool, newline: bool) -> Self {
+        Self {
+            items: vec![ClassItem::Builtin(builtin)],
+            negated,
+            newline,
+        }
+    }
+
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        // Case is ignored for characters and ranges, but not builtin classes
+        let contains = |c: char| {
+            self.items.iter().any(|item| match *item {
+                ClassItem::Builtin(_) => false,
+                ClassItem::Char(other) => c == other,
+                ClassItem::Range(start, end) => start <= c && c <= end,
+            })
+        };
+        let found = self.items.iter().any(|item| match *item {
+            ClassItem::Builtin(builtin) => builtin.matches(c),
+            _ => false,
+        }) || contains(c)
+            || (ignore_case && (contains(lower(c)) || c.to_uppercase().any(contains)));
+        found != self.negated
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Node {
+    /// Match any character, and end of line if newline is true
+    Any { newline: bool },
+    /// Match text of a previous group
+    Backref(usize),
+    /// Match single character
+    Char(char),
+    /// Match character class
+    Class(Class),
+    /// Match end of line
+    End,
+    /// Match end of file
+    FileEnd,
+    /// Match start of file
+    FileStart,
+    /// Match alternatives, capturing if index is set
+    Group {
+        index: Option<usize>,
+        alternatives: Vec<Vec<Node>>,
+    },
+    /// Match without backtracking into the nodes
+    Atomic(Vec<Node>),
+    /// Match if nodes match (or do not match if negated) at this position
+    LookAhead { nodes: Vec<Node>, negated: bool },
+    /// Match if nodes match (or do not match if negated) ending at this position
+    LookBehind { nodes: Vec<Node>, negated: bool },
+    /// Set start of match
+    MatchStart,
+    /// Set end of match
+    MatchEnd,
+    /// Match end of line, if not on last line
+    Newline,
+    /// Match node a number of times
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+    /// Match start of line
+    Start,
+    /// Match end of word
+    WordEnd,
+    /// Match start of word
+    WordStart,
+}
+
+impl Node {
+    /// Returns true if node always matches one character or end of line
+    fn single(&self) -> bool {
+        matches!(
+            self,
+            Self::Any { .. } | Self::Char(_) | Self::Class(_) | Self::Newline
+        )
+    }
+}
+
+/// Magic level, see `:help /magic`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Magic {
+    Very,
+    On,
+    Off,
+    VeryOff,
+}
+
+impl Magic {
+    /// Characters that can have a special meaning
+    const SPECIAL: &'static str = "^$.*[~()|+?={@%<>&";
+
+    /// Returns true if the character is special, given if it was escaped
+    fn special(self, c: char, escaped: bool) -> bool {
+        if !Self::SPECIAL.contains(c) {
+            return false;
+        }
+        let unescaped = match self {
+            Self::Very => true,
+            Self::On => "^$.*[~".contains(c),
+            Self::Off => "^$".contains(c),
+            Self::VeryOff => "^$".contains(c),
+        };
+        unescaped != escaped
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Token {
+    /// Literal character
+    Literal(char),
+    /// Character with special meaning
+    Special(char),
+    /// Backslash followed by a character that is not magic dependent
+    Escape(char),
+}
+
+#[derive(Clone, Debug)]
+struct Lexer<'a> {
+    chars: Chars<'a>,
+    magic: Magic,
+    ignore_case: Option<bool>,
+}
+
+impl<'a> Lexer<'a> {
+    fn next(&mut self) -> Result<Option<Token>, RegexError> {
+        loop {
+            let c = match self.chars.next() {
+                Some(c) => c,
+                None => return Ok(None),
+            };
+            if c != '\\' {
+                return Ok(Some(if self.magic.special(c, false) {
+                    Token::Special(c)
+                } else {
+                    Token::Literal(c)
+                }));
+            }
+
+            let c = self.chars.next().ok_or(RegexError::TrailingBackslash)?;
+            match c {
+                'v' => self.magic = Magic::Very,
+                'm' => self.magic = Magic::On,
+                'M' => self.magic = Magic::Off,
+                'V' => self.magic = Magic::VeryOff,
+                'c' => self.ignore_case = Some(true),
+                'C' => {
+                    if self.ignore_case.is_none() {
+                        self.ignore_case = Some(false);
+                    }
+                }
+                _ => {
+                    return Ok(Some(if Magic::SPECIAL.contains(c) {
+                        if self.magic.special(c, true) {
+                            Token::Special(c)
+                        } else {
+                            Token::Literal(c)
+                        }
+                    } else if c.is_ascii_alphanumeric() || c == '_' {
+                        Token::Escape(c)
+                    } else {
+                        Token::Literal(c)
+                    }));
+                }
+            }
+        }
+    }
+
+    fn peek(&self) -> Result<Option<Token>, RegexError> {
+        self.clone().next()
+    }
+
+    /// Read a character without interpretation
+    fn raw(&mut self) -> Option<char> {
+        self.chars.next()
+    }
+
+    /// Read a character without interpretation if it matches
+    fn raw_if(&mut self, c: char) -> bool {
+        let mut chars = self.chars.clone();
+        if chars.next() == Some(c) {
+            self.chars = chars;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Read a number without interpretation
+    fn raw_number(&mut self, radix: u32, max_len: usize) -> Option<u32> {
+        let mut number: Option<u32> = None;
+        for _ in 0..max_len {
+            let mut chars = self.chars.clone();
+            let digit = match chars.next().and_then(|c| c.to_digit(radix)) {
+                Some(digit) => digit,
+                None => break,
+            };
+            self.chars = chars;
+            number = Some(
+                number
+                    .unwrap_or(0)
+                    .saturating_mul(radix)
+                    .saturating_add(digit),
+            );
+        }
+        number
+    }
+}
+
+#[derive(Debug)]
+struct Compiler<'a> {
+    lexer: Lexer<'a>,
+    groups: usize,
+    last_substitute: &'a str,
+}
+
+impl<'a> Compiler<'a> {
+    /// Parse alternatives separated by `\|`
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, RegexError> {
+        let mut alternatives = Vec::new();
+        loop {
+            alternatives.push(self.branch()?);
+            if self.lexer.peek()? == Some(Token::Special('|')) {
+                self.lexer.next()?;
+            } else {
+                return Ok(alternatives);
+            }
+        }
+    }
+
+    /// Parse concats separated by `\&`, all but the last are matched as lookahead
+    fn branch(&mut self) -> Result<Vec<Node>, RegexError> {
+        let mut nodes = Vec::new();
+        loop {
+            let concat = self.concat()?;
+            if self.lexer.peek()? == Some(Token::Special('&')) {
+                self.lexer.next()?;
+                nodes.push(Node::LookAhead {
+                    nodes: concat,
+                    negated: false,
+                });
+            } else {
+                nodes.extend(concat);
+                return Ok(nodes);
+            }
+        }
+    }
+
+    /// Parse pieces until end of branch
+    fn concat(&mut self) -> Result<Vec<Node>, RegexError> {
+        let mut nodes = Vec::new();
+        loop {
+            let token = match self.lexer.peek()? {
+                None | Some(Token::Special('|' | '&' | ')')) => return Ok(nodes),
+                Some(token) => token,
+            };
+            self.lexer.next()?;
+            let node = self.atom(token, nodes.is_empty())?;
+            let node = self.multi(node)?;
+            nodes.push(node);
+        }
+    }
+
+    /// Parse an atom from token
+    fn atom(&mut self, token: Token, first: bool) -> Result<Node, RegexError> {
+        Ok(match token {
+            Token::Literal(c) => Node::Char(c),
+            Token::Special(c) => match c {
+                '^' if first => Node::Start,
+                '$' if self.at_end()? => Node::End,
+                '.' => Node::Any { newline: false },
+                '[' => self.class(false)?,
+                '~' => Node::Group {
+                    index: None,
+                    alternatives: vec![self.last_substitute.chars().map(Node::Char).collect()],
+                },
+                '(' => self.group(true)?,
+                '%' => self.percent()?,
+                '<' => Node::WordStart,
+                '>' => Node::WordEnd,
+                ')' => return Err(RegexError::UnmatchedCloseParen),
+                // Anything else is literal when it cannot have a special meaning
+                _ => Node::Char(c),
+            },
+            Token::Escape(c) => match c {
+                'n' => Node::Newline,
+                't' => Node::Char('\t'),
+                'e' => Node::Char('\x1B'),
+                'r' => Node::Char('\r'),
+                'b' => Node::Char('\x08'),
+                '1'..='9' => {
+                    let group = c.to_digit(10).map_or(0, |digit| digit as usize);
+                    if group > self.groups {
+                        return Err(RegexError::InvalidBackref(group));
+                    }
+                    Node::Backref(group)
+                }
+                'z' => match self.lexer.raw() {
+                    Some('s') => Node::MatchStart,
+                    Some('e') => Node::MatchEnd,
+                    _ => return Err(RegexError::InvalidZ),
+                },
+                '_' => match self.lexer.raw() {
+                    Some('.') => Node::Any { newline: true },
+                    Some('[') => self.class(true)?,
+                    Some('^') => Node::Start,
+                    Some('$') => Node::End,
+                    Some(c) => match Builtin::from_escape(c) {
+                        Some((builtin, negated)) => {
+                            Node::Class(Class::builtin(builtin, negated, true))
+                        }
+                        None => Node::Char(c),
+                    },
+                    None => return Err(RegexError::TrailingBackslash),
+                },
+                _ => match Builtin::from_escape(c) {
+                    Some((builtin, negated)) => {
+                        Node::Class(Class::builtin(builtin, negated, false))
+                    }
+                    None => Node::Char(c),
+                },
+            },
+        })
+    }
+
+    /// Returns true if the next token ends the branch, so `$` is end of line
+    fn at_end(&self) -> Result<bool, RegexError> {
+        Ok(matches!(
+            self.lexer.peek()?,
+            None | Some(Token::Special('|' | '&' | ')')) | Some(Token::Escape('n'))
+        ))
+    }
+
+    /// Parse group after `\(` or `\%(`
+    fn group(&mut self, capture: bool) -> Result<Node, RegexError> {
+        let index = if capture {
+            self.groups = self.groups.saturating_add(1);
+            if self.groups >= REGEX_GROUPS {
+                return Err(RegexError::TooManyGroups);
+            }
+            Some(self.groups)
+        } else {
+            None
+        };
+        let alternatives = self.alternatives()?;
+        match self.lexer.next()? {
+            Some(Token::Special(')')) => Ok(Node::Group {
+                index,
+                alternatives,
+            }),
+            _ => Err(RegexError::UnmatchedOpenParen),
+        }
+    }
+
+    /// Parse item after `\%`
+    fn percent(&mut self) -> Result<Node, RegexError> {
+        let (radix, max_len) = match self.lexer.raw() {
+            Some('(') => return self.group(false),
+            Some('^') => return Ok(Node::FileStart),
+            Some('$') => return Ok(Node::FileEnd),
+            Some('d') => (10, usize::MAX),
+            Some('o') => (8, 4),
+            Some('x') => (16, 2),
+            Some('u') => (16, 4),
+            Some('U') => (16, 8),
+            _ => return Err(RegexError::InvalidPercent),
+        };
+        self.lexer
+            .raw_number(radix, max_len)
+            .and_then(char::from_u32)
+            .map(Node::Char)
+            .ok_or(RegexError::InvalidPercent)
+    }
+
+    /// Parse collection after `[`, returning a literal `[` if it is not closed
+    fn class(&mut self, newline: bool) -> Result<Node, RegexError> {
+        let start = self.lexer.clone();
+        match self.class_items(newline) {
+            Some(class) => Ok(Node::Class(class)),
+            None => {
+                self.lexer = start;
+                Ok(Node::Char('['))
+            }
+        }
+    }
+
+    fn class_items(&mut self, newline: bool) -> Option<Class> {
+        let mut class = Class {
+            items: Vec::new(),
+            negated: self.lexer.raw_if('^'),
+            newline,
+        };
+        let mut first = true;
+        loop {
+            let c = self.lexer.raw()?;
+            let item_c = match c {
+                ']' if !first => return Some(class),
+                '[' if self.lexer.raw_if(':') => {
+                    let rest = self.lexer.chars.as_str();
+                    let end = rest.find(":]")?;
+                    let builtin = Builtin::from_name(rest.get(..end)?)?;
+                    self.lexer.chars = rest.get(end.checked_add(2)?..)?.chars();
+                    class.items.push(ClassItem::Builtin(builtin));
+                    first = false;
+                    continue;
+                }
+                '\\' => match self.lexer.raw()? {
+                    'e' => '\x1B',
+                    't' => '\t',
+                    'r' => '\r',
+                    'b' => '\x08',
+                    'n' => {
+                        class.newline = true;
+                        first = false;
+                        continue;
+                    }
+                    'd' => char::from_u32(self.lexer.raw_number(10, usize::MAX)?)?,
+                    'o' => char::from_u32(self.lexer.raw_number(8, 4)?)?,
+                    'x' => char::from_u32(self.lexer.raw_number(16, 2)?)?,
+                    'u' => char::from_u32(self.lexer.raw_number(16, 4)?)?,
+                    'U' => char::from_u32(self.lexer.raw_number(16, 8)?)?,
+                    c @ ('\\' | ']' | '^' | '-') => c,
+                    // Backslash is literal before other characters
+                    c => {
+                        class.items.push(ClassItem::Char('\\'));
+                        c
+                    }
+                },
+                c => c,
+            };
+            first = false;
+
+            // Check for range, a trailing dash is literal
+            let mut chars = self.lexer.chars.clone();
+            if chars.next() == Some('-') && !matches!(chars.next(), Some(']') | None) {
+                self.lexer.raw();
+                let end = match self.lexer.raw()? {
+                    '\\' => self.lexer.raw()?,
+                    c => c,
+                };
+                class.items.push(ClassItem::Range(item_c, end));
+            } else {
+                class.items.push(ClassItem::Char(item_c));
+            }
+        }
+    }
+
+    /// Parse any multis following an atom
+    fn multi(&mut self, mut node: Node) -> Result<Node, RegexError> {
+        loop {
+            let (min, max, greedy) = match self.lexer.peek()? {
+                Some(Token::Special('*')) => (0, None, true),
+                Some(Token::Special('+')) => (1, None, true),
+                Some(Token::Special('=' | '?')) => (0, Some(1), true),
+                Some(Token::Special('{')) => {
+                    self.lexer.next()?;
+                    let (min, max, greedy) = self.brace()?;
+                    node = Node::Repeat {
+                        node: Box::new(node),
+                        min,
+                        max,
+                        greedy,
+                    };
+                    continue;
+                }
+                Some(Token::Special('@')) => {
+                    self.lexer.next()?;
+                    node = self.lookaround(node)?;
+                    continue;
+                }
+                _ => return Ok(node),
+            };
+            self.lexer.next()?;
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    /// Parse bounds after `\{`
+    fn brace(&mut self) -> Result<(usize, Option<usize>, bool), RegexError> {
+        let greedy = !self.lexer.raw_if('-');
+        let min = self.lexer.raw_number(10, usize::MAX);
+        let max = if self.lexer.raw_if(',') {
+            self.lexer.raw_number(10, usize::MAX)
+        } else {
+            // Exact count, or unbounded if no count
+            min
+        };
+        self.lexer.raw_if('\\');
+        if !self.lexer.raw_if('}') {
+            return Err(RegexError::InvalidBrace);
+        }
+        Ok((
+            min.map_or(0, |min| min as usize),
+            max.map(|max| max as usize),
+            greedy,
+        ))
+    }
+
+    /// Parse lookaround after `\@`
+    fn lookaround(&mut self, node: Node) -> Result<Node, RegexError> {
+        // Ignore byte limit for look behind
+        self.lexer.raw_number(10, usize::MAX);
+        let nodes = vec![node];
+        Ok(match self.lexer.raw() {
+            Some('>') => Node::Atomic(nodes),
+            Some('=') => Node::LookAhead {
+                nodes,
+                negated: false,
+            },
+            Some('!') => Node::LookAhead {
+                nodes,
+                negated: true,
+            },
+            Some('<') => match self.lexer.raw() {
+                Some('=') => Node::LookBehind {
+                    nodes,
+                    negated: false,
+                },
+                Some('!') => Node::LookBehind {
+                    nodes,
+                    negated: true,
+                },
+                _ => return Err(RegexError::InvalidLookaround),
+            },
+            _ => return Err(RegexError::InvalidLookaround),
+        })
+    }
+}
+
+/// Returns true if pattern has upper case characters, ignoring escaped characters
+fn has_upper(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+type Continuation<'k, M> = &'k mut dyn FnMut(&mut M, TextPos) -> bool;
+
+#[derive(Debug)]
+struct Matcher<'a, L: AsRef<str>> {
+    lines: &'a [L],
+    ignore_case: bool,
+    groups: [Option<(TextPos, TextPos)>; REGEX_GROUPS],
+    match_start: Option<TextPos>,
+    match_end: Option<TextPos>,
+    steps: usize,
+    budget: usize,
+    depth: usize,
+}
+
+impl<'a, L: AsRef<str>> Matcher<'a, L> {
+    fn new(lines: &'a [L], ignore_case: bool) -> Self {
+        Self {
+            lines,
+            ignore_case,
+            groups: [None; REGEX_GROUPS],
+            match_start: None,
+            match_end: None,
+            steps: 0,
+            budget: REGEX_MAX_STEPS,
+            depth: 0,
+        }
+    }
+
+    /// Returns true if the step budget is used up or the depth limit was reached
+    fn exhausted(&self) -> bool {
+        self.steps > self.budget
+    }
+
+    /// Match regex nodes starting exactly at position, sharing the step budget with
+    /// previous attempts after adding [`REGEX_START_STEPS`] to it
+    fn match_at(
+        &mut self,
+        nodes: &[Node],
+        pos: TextPos,
+    ) -> Result<Option<RegexMatch>, RegexExhausted> {
+        self.budget = self.budget.saturating_add(REGEX_START_STEPS);
+        self.groups = [None; REGEX_GROUPS];
+        self.match_start = None;
+        self.match_end = None;
+        let mut found = None;
+        self.nodes(nodes, pos, &mut |m, _end| {
+            found = Some((m.groups, m.match_start, m.match_end));
+            true
+        });
+        if self.exhausted() {
+            return Err(RegexExhausted);
+        }
+        let Some((mut groups, match_start, match_end)) = found else {
+            return Ok(None);
+        };
+        let Some((start, end)) = groups.first().copied().flatten() else {
+            return Ok(None);
+        };
+        let start = match_start.unwrap_or(start);
+        let end = match_end.unwrap_or(end).max(start);
+        if let Some(group) = groups.first_mut() {
+            *group = Some((start, end));
+        }
+        Ok(Some(RegexMatch { start, end, groups }))
+    }
+
+    /// Find the first match starting in the line of position, at or after it
+    fn find_in_line(
+        &mut self,
+        nodes: &[Node],
+        from: TextPos,
+    ) -> Result<Option<RegexMatch>, RegexExhausted> {
+        let Some(line) = self.line(from.line) else {
+            return Ok(None);
+        };
+        let starts = line
+            .get(from.index..)
+            .unwrap_or("")
+            .char_indices()
+            .map(|(index, _)| from.index.saturating_add(index))
+            .chain(Some(line.len()));
+        for index in starts {
+            let start = TextPos {
+                line: from.line,
+                index,
+            };
+            if let Some(found) = self.match_at(nodes, start)? {
+                return Ok(Some(found));
+            }
+        }
+        Ok(None)
+    }
+    fn line(&self, line: usize) -> Option<&'a str> {
+        self.lines.get(line).map(|line| line.as_ref())
+    }
+
+    /// Character at position and the position after it
+    fn next_char(&self, pos: TextPos) -> Option<(char, TextPos)> {
+        let c = self.line(pos.line)?.get(pos.index..)?.chars().next()?;
+        Some((
+            c,
+            TextPos {
+                line: pos.line,
+                index: pos.index.checked_add(c.len_utf8())?,
+            },
+        ))
+    }
+
+    /// Character before position
+    fn prev_char(&self, pos: TextPos) -> Option<char> {
+        self.line(pos.line)?.get(..pos.index)?.chars().next_back()
+    }
+
+    /// Start of next line, if position is at end of line
+    fn next_line(&self, pos: TextPos) -> Option<TextPos> {
+        if pos.index != self.line(pos.line)?.len() {
+            return None;
+        }
+        let line = pos.line.checked_add(1)?;
+        self.line(line)?;
+        Some(TextPos { line, index: 0 })
+    }
+
+    fn keyword(c: Option<char>) -> bool {
+        c.is_some_and(|c| Builtin::Keyword.matches(c))
+    }
+
+    fn char_eq(&self, a: char, b: char) -> bool {
+        a == b || (self.ignore_case && lower(a) == lower(b))
+    }
+
+    /// Match a node that is always one character or end of line
+    fn single(&self, node: &Node, pos: TextPos) -> Option<TextPos> {
+        match node {
+            Node::Any { newline } => match self.next_char(pos) {
+                Some((_, next)) => Some(next),
+                None if *newline => self.next_line(pos),
+                None => None,
+            },
+            Node::Char(c) => {
+                let (other, next) = self.next_char(pos)?;
+                self.char_eq(*c, other).then_some(next)
+            }
+            Node::Class(class) => match self.next_char(pos) {
+                Some((c, next)) => class.matches(c, self.ignore_case).then_some(next),
+                None if class.newline => self.next_line(pos),
+                None => None,
+            },
+            Node::Newline => self.next_line(pos),
+            _ => None,
+        }
+    }
+
+    /// Match nodes at position, calling k with the end position until it returns true
+    fn nodes(&mut self, nodes: &[Node], pos: TextPos, k: Continuation<Self>) -> bool {
+        if self.depth >= REGEX_MAX_DEPTH {
+            // Give up on the whole search, a partial result would be wrong
+            self.steps = usize::MAX;
+        }
+        if self.exhausted() {
+            return false;
+        }
+        self.depth = self.depth.saturating_add(1);
+        let matched = self.nodes_inner(nodes, pos, k);
+        self.depth = self.depth.saturating_sub(1);
+        matched
+    }
+
+    fn nodes_inner(&mut self, mut nodes: &[Node], mut pos: TextPos, k: Continuation<Self>) -> bool {
+        // Match single characters without recursion
+        while let Some((node, rest)) = nodes.split_first().filter(|(node, _)| node.single()) {
+            self.steps = self.steps.saturating_add(1);
+            if self.exhausted() {
+                return false;
+            }
+            match self.single(node, pos) {
+                Some(next) => {
+                    nodes = rest;
+                    pos = next;
+                }
+                None => return false,
+            }
+        }
+
+        self.steps = self.steps.saturating_add(1);
+        if self.exhausted() {
+            return false;
+        }
+
+        let (node, rest) = match nodes.split_first() {
+            Some(some) => some,
+            None => return k(self, pos),
+        };
+
+        match node {
+            Node::Backref(group) => {
+                let (start, end) = match self.groups.get(*group).copied().flatten() {
+                    Some(some) => some,
+                    // Vim matches empty string for unset groups
+                    None => return self.nodes(rest, pos, k),
+                };
+                let mut from = start;
+                let mut to = pos;
+                while from < end {
+                    match (self.next_char(from), self.next_char(to)) {
+                        (Some((a, a_next)), Some((b, b_next))) => {
+                            if !self.char_eq(a, b) {
+                                return false;
+                            }
+                            from = a_next;
+                            to = b_next;
+                        }
+                        (None, None) => match (self.next_line(from), self.next_line(to)) {
+                            (Some(a_next), Some(b_next)) => {
+                                from = a_next;
+                                to = b_next;
+                            }
+                            _ => return false,
+                        },
+                        _ => return false,
+                    }
+                }
+                self.nodes(rest, to, k)
+            }
+            Node::End => {
+                if self.next_char(pos).is_none() {
+                    self.nodes(rest, pos, k)
+                } else {
+                    false
+                }
+            }
+            Node::FileEnd => {
+                if self.next_char(pos).is_none() && self.next_line(pos).is_none() {
+                    self.nodes(rest, pos, k)
+                } else {
+                    false
+                }
+            }
+            Node::FileStart => {
+                if pos == TextPos::default() {
+                    self.nodes(rest, pos, k)
+                } else {
+                    false
+                }
+            }
+            Node::Group {
+                index,
+                alternatives,
+            } => {
+                let index = *index;
+                for alternative in alternatives.iter() {
+                    let matched = self.nodes(alternative, pos, &mut |m: &mut Self, end| {
+                        let old = match index.and_then(|index| m.groups.get_mut(index)) {
+                            Some(group) => group.replace((pos, end)),
+                            None => None,
+                        };
+                        if m.nodes(rest, end, k) {
+                            return true;
+                        }
+                        if let Some(group) = index.and_then(|index| m.groups.get_mut(index)) {
+                            *group = old;
+                        }
+                        false
+                    });
+                    if matched {
+                        return true;
+                    }
+                }
+                false
+            }
+            Node::Atomic(nodes) => {
+                let mut end_opt = None;
+                self.nodes(nodes, pos, &mut |_, end| {
+                    end_opt = Some(end);
+                    true
+                });
+                match end_opt {
+                    Some(end) => self.nodes(rest, end, k),
+                    None => false,
+                }
+            }
+            Node::LookAhead { nodes, negated } => {
+                let found = self.nodes(nodes, pos, &mut |_, _| true);
+                if found != *negated {
+                    self.nodes(rest, pos, k)
+                } else {
+                    false
+                }
+            }
+            Node::LookBehind { nodes, negated } => {
+                // Try every start in the current line, nearest first
+                let mut found = false;
+                let line = self.line(pos.line).unwrap_or("");
+                let starts = line
+                    .get(..pos.index)
+                    .unwrap_or("")
+                    .char_indices()
+                    .map(|(index, _)| index)
+                    .chain(Some(pos.index))
+                    .rev();
+                for index in starts {
+                    let start = TextPos {
+                        line: pos.line,
+                        index,
+                    };
+                    if self.nodes(nodes, start, &mut |_, end| end == pos) {
+                        found = true;
+                        break;
+                    }
+                }
+                if found != *negated {
+                    self.nodes(rest, pos, k)
+                } else {
+                    false
+                }
+            }
+            Node::MatchStart => {
+                let old = self.match_start.replace(pos);
+                if self.nodes(rest, pos, k) {
+                    return true;
+                }
+                self.match_start = old;
+                false
+            }
+            Node::MatchEnd => {
+                let old = self.match_end.replace(pos);
+                if self.nodes(rest, pos, k) {
+                    return true;
+                }
+                self.match_end = old;
+                false
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                if node.single() {
+                    // Find all positions without recursion
+                    let mut positions = vec![pos];
+                    let mut last = pos;
+                    while max.is_none_or(|max| positions.len() <= max) {
+                        match self.single(node, last) {
+                            Some(next) => {
+                                positions.push(next);
+                                last = next;
+                            }
+                            None => break,
+                        }
+                    }
+                    let counts = *min..positions.len();
+                    if *greedy {
+                        for count in counts.rev() {
+                            if let Some(&end) = positions.get(count) {
+                                if self.nodes(rest, end, k) {
+                                    return true;
+                                }
+                            }
+                        }
+                    } else {
+                        for count in counts {
+                            if let Some(&end) = positions.get(count) {
+                                if self.nodes(rest, end, k) {
+                                    return true;
+                                }
+                            }
+                        }
+                    }
+                    false
+                } else {
+                    self.repeat(node, *min, *max, *greedy, pos, rest, k)
+                }
+            }
+            Node::Start => {
+                if pos.index == 0 {
+                    self.nodes(rest, pos, k)
+                } else {
+                    false
+                }
+            }
+            Node::WordEnd => {
+                if Self::keyword(self.prev_char(pos))
+                    && !Self::keyword(self.next_char(pos).map(|x| x.0))
+                {
+                    self.nodes(rest, pos, k)
+                } else {
+                    false
+                }
+            }
+            Node::WordStart => {
+                if !Self::keyword(self.prev_char(pos))
+                    && Self::keyword(self.next_char(pos).map(|x| x.0))
+                {
+                    self.nodes(rest, pos, k)
+                } else {
+                    false
+                }
+            }
+            Node::Any { .. } | Node::Char(_) | Node::Class(_) | Node::Newline => false,
+        }
+    }
+
+    /// Groups and match bounds, saved to undo the effects of a failed branch
+    fn state(&self) -> MatcherState {
+        (self.groups, self.match_start, self.match_end)
+    }
+
+    fn restore(&mut self, state: MatcherState) {
+        (self.groups, self.match_start, self.match_end) = state;
+    }
+
+    /// Match a repeated node that may not be a single character, keeping the repetitions
+    /// to backtrack into on the heap instead of recursing
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(
+        &mut self,
+        node: &Node,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+        pos: TextPos,
+        rest: &[Node],
+        k: Continuation<Self>,
+    ) -> bool {
+        let mut frames = vec![RepeatFrame {
+            pos,
+            count: 0,
+            tried: 0,
+            rest_tried: false,
+            state: self.state(),
+        }];
+        while let Some(frame) = frames.last_mut() {
+            if self.exhausted() {
+                return false;
+            }
+            self.restore(frame.state);
+            let (pos, count) = (frame.pos, frame.count);
+
+            // Lazy repeats try the rest first, greedy repeats after all longer matches
+            let can_repeat = max.is_none_or(|max| count < max) && frame.tried != usize::MAX;
+            if !frame.rest_tried && (!greedy || !can_repeat) {
+                frame.rest_tried = true;
+                if count >= min && self.nodes(rest, pos, k) {
+                    return true;
+                }
+                continue;
+            }
+            if !can_repeat {
+                frames.pop();
+                continue;
+            }
+
+            // Find the next end of one more repetition, which leaves its groups set
+            let skip = frame.tried;
+            frame.tried = frame.tried.saturating_add(1);
+            let mut ends = 0;
+            let mut found = None;
+            self.nodes(slice::from_ref(node), pos, &mut |_, end| {
+                // Stop repeating empty matches
+                if end == pos && count >= min {
+                    return false;
+                }
+                if ends < skip {
+                    ends = ends.saturating_add(1);
+                    return false;
+                }
+                found = Some(end);
+                true
+            });
+            match found {
+                Some(end) => {
+                    if frames.len() >= REGEX_MAX_REPEATS {
+                        // Give up on the whole search, a partial result would be wrong
+                        self.steps = usize::MAX;
+                        return false;
+                    }
+                    frames.push(RepeatFrame {
+                        pos: end,
+                        count: count.saturating_add(1),
+                        tried: 0,
+                        rest_tried: false,
+                        state: self.state(),
+                    });
+                }
+                None => frame.tried = usize::MAX,
+            }
+        }
+        false
+    }
+}
+
+type MatcherState = (
+    [Option<(TextPos, TextPos)>; REGEX_GROUPS],
+    Option<TextPos>,
+    Option<TextPos>,
+);
+
+/// One repetition of [`Matcher::repeat`] that can be backtracked into
+#[derive(Debug)]
+struct RepeatFrame {
+    pos: TextPos,
+    count: usize,
+    /// Number of ends of the next repetition that were tried, `usize::MAX` when all were
+    tried: usize,
+    rest_tried: bool,
+    state: MatcherState,
+}
+
+/// A compiled vim regular expression
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Regex {
+    nodes: Vec<Node>,
+    ignore_case: bool,
+}
+
+impl Regex {
+    /// Compile pattern with default options
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pattern is invalid
+    pub fn new(pattern: &str) -> Result<Self, RegexError> {
+        Self::with_options(pattern, &RegexOptions::default())
+    }
+
+    /// Compile pattern with options
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pattern is invalid
+    pub fn with_options(pattern: &str, options: &RegexOptions) -> Result<Self, RegexError> {
+        let mut compiler = Compiler {
+            lexer: Lexer {
+                chars: pattern.chars(),
+                magic: if options.nomagic {
+                    Magic::Off
+                } else {
+                    Magic::On
+                },
+                ignore_case: None,
+            },
+            groups: 0,
+            last_substitute: options.last_substitute.as_deref().unwrap_or(""),
+        };
+        let alternatives = compiler.alternatives()?;
+        if compiler.lexer.next()?.is_some() {
+            return Err(RegexError::UnmatchedCloseParen);
+        }
+        let ignore_case = compiler
+            .lexer
+            .ignore_case
+            .unwrap_or_else(|| options.ignore_case && !(options.smart_case && has_upper(pattern)));
+        Ok(Self {
+            nodes: vec![Node::Group {
+                index: Some(0),
+                alternatives,
+            }],
+            ignore_case,
+        })
+    }
+
+    /// Escape text so it matches literally in magic mode, for example to search for the
+    /// word under the cursor with `*`
+    pub fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            if "\\/.*$^~[]".contains(c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+
+    /// Returns true if the regex ignores case, after applying smart case and `\c`
+    pub fn ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+
+    /// Match starting exactly at position
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the search gave up, see [`RegexExhausted`]
+    pub fn match_at<L: AsRef<str>>(
+        &self,
+        lines: &[L],
+        pos: TextPos,
+    ) -> Result<Option<RegexMatch>, RegexExhausted> {
+        Matcher::new(lines, self.ignore_case).match_at(&self.nodes, pos)
+    }
+
+    /// Find the first match starting at or after position
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the search gave up, see [`RegexExhausted`]
+    pub fn find_from<L: AsRef<str>>(
+        &self,
+        lines: &[L],
+        from: TextPos,
+    ) -> Result<Option<RegexMatch>, RegexExhausted> {
+        let mut matcher = Matcher::new(lines, self.ignore_case);
+        let mut pos = from;
+        while pos.line < lines.len() {
+            if let Some(found) = matcher.find_in_line(&self.nodes, pos)? {
+                return Ok(Some(found));
+            }
+            pos = TextPos {
+                line: pos.line.saturating_add(1),
+                index: 0,
+            };
+        }
+        Ok(None)
+    }
+
+    /// Find the last match starting before position
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the search gave up, see [`RegexExhausted`]
+    pub fn find_before<L: AsRef<str>>(
+        &self,
+        lines: &[L],
+        before: TextPos,
+    ) -> Result<Option<RegexMatch>, RegexExhausted> {
+        let mut matcher = Matcher::new(lines, self.ignore_case);
+        let Some(last) = lines.len().checked_sub(1) else {
+            return Ok(None);
+        };
+        let mut line_i = before.line.min(last);
+        loop {
+            let Some(line) = lines.get(line_i).map(|line| line.as_ref()) else {
+                return Ok(None);
+            };
+            let end = if line_i == before.line {
+                before.index.min(line.len())
+            } else {
+                line.len().saturating_add(1)
+            };
+            let starts = line
+                .char_indices()
+                .map(|(index, _)| index)
+                .chain(Some(line.len()))
+                .filter(|&index| index < end)
+                .collect::<Vec<_>>();
+            for &index in starts.iter().rev() {
+                let start = TextPos {
+                    line: line_i,
+                    index,
+                };
+                if let Some(found) = matcher.match_at(&self.nodes, start)? {
+                    return Ok(Some(found));
+                }
+            }
+            line_i = match line_i.checked_sub(1) {
+                Some(some) => some,
+                None => return Ok(None),
+            };
+        }
+    }
+
+    /// Iterate over non-overlapping matches in one line, for substitute. Iteration stops
+    /// early if the search gives up, see [`RegexLineIter::exhausted`]
+    pub fn find_iter<'a, L: AsRef<str>>(
+        &'a self,
+        lines: &'a [L],
+        line: usize,
+    ) -> RegexLineIter<'a, L> {
+        RegexLineIter {
+            regex: self,
+            matcher: Matcher::new(lines, self.ignore_case),
+            pos: TextPos { line, index: 0 },
+            last_end: None,
+            done: false,
+            exhausted: false,
+        }
+    }
+}
+
+/// Iterator over non-overlapping matches starting in one line
+#[derive(Debug)]
+pub struct RegexLineIter<'a, L: AsRef<str>> {
+    regex: &'a Regex,
+    matcher: Matcher<'a, L>,
+    pos: TextPos,
+    last_end: Option<TextPos>,
+    done: bool,
+    exhausted: bool,
+}
+
+impl<L: AsRef<str>> RegexLineIter<'_, L> {
+    /// Returns true if iteration stopped because the search gave up, see [`RegexExhausted`]
+    pub fn exhausted(&self) -> bool {
+        self.exhausted
+    }
+}
+
+impl<'a, L: AsRef<str>> Iterator for RegexLineIter<'a, L> {
+    type Item = RegexMatch;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let found = match self.matcher.find_in_line(&self.regex.nodes, self.pos) {
+                Ok(Some(some)) => some,
+                Ok(None) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(RegexExhausted) => {
+                    self.done = true;
+                    self.exhausted = true;
+                    return None;
+                }
+            };
+
+            let empty = found.end <= found.start;
+            if found.end.line != self.pos.line {
+                // Match continues on following lines, so this is the last one
+                self.done = true;
+            } else if !empty {
+                self.pos = found.end;
+            } else {
+                // Empty match, the next match starts at least one character later
+                let line = self.matcher.line(self.pos.line)?;
+                match line.get(found.start.index..).and_then(|x| x.chars().next()) {
+                    Some(c) => self.pos.index = found.start.index.saturating_add(c.len_utf8()),
+                    None => self.done = true,
+                }
+            }
+
+            // Like vim, an empty match directly after the previous match is skipped
+            if empty && self.last_end == Some(found.start) {
+                continue;
+            }
+            self.last_end = Some(found.end);
+            return Some(found);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::indexing_slicing, clippy::unwrap_used)]
+mod tests {
+    use alloc::{string::String, vec};
+
+    use super::*;
+
+    fn pos(line: usize, index: usize) -> TextPos {
+        TextPos { line, index }
+    }
+
+    #[test]
+    fn repeat_group() {
+        let regex = Regex::new(r"\(ab\)*c").unwrap();
+        let line = "ab".repeat(100) + "c";
+        let found = regex
+            .match_at(&[line.as_str()], pos(0, 0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.end, pos(0, line.len()));
+        assert_eq!(found.groups[1], Some((pos(0, 198), pos(0, 200))));
+    }
+
+    #[test]
+    fn repeat_backtrack() {
+        let end = |pattern: &str, line: &str| {
+            let regex = Regex::new(pattern).unwrap();
+            regex
+                .match_at(&[line], pos(0, 0))
+                .unwrap()
+                .map(|found| found.end.index)
+        };
+        assert_eq!(end(r"\(a\|ab\)*c", "abac"), Some(4));
+        assert_eq!(end(r"\(a*\)*b", "aab"), Some(3));
+        assert_eq!(end(r"\(ab\)\{-1,}", "ababab"), Some(2));
+        assert_eq!(end(r"\(ab\)\{-1,}$", "ababab"), Some(6));
+        assert_eq!(end(r"\(ab\)\{2}", "ababab"), Some(4));
+        assert_eq!(end(r"\(ab\)\{4}", "ababab"), None);
+        assert_eq!(end(r"\(ab\)*$", &"ab".repeat(20_000)), Some(40_000));
+
+        let regex = Regex::new(r"\(\(a\)\|b\)*").unwrap();
+        let found = regex.match_at(&["ab"], pos(0, 0)).unwrap().unwrap();
+        assert_eq!(found.groups[1], Some((pos(0, 1), pos(0, 2))));
+        assert_eq!(found.groups[2], Some((pos(0, 0), pos(0, 1))));
+    }
+
+    #[test]
+    fn repeat_group_deep() {
+        // Must not overflow the stack
+        let regex = Regex::new(r"\(ab\)*").unwrap();
+        let lines = ["ab".repeat(200_000)];
+        assert_eq!(regex.match_at(&lines, pos(0, 0)), Err(RegexExhausted));
+        assert_eq!(regex.find_from(&lines, pos(0, 0)), Err(RegexExhausted));
+
+        let regex = Regex::new(r"\(ab\)\{-}$").unwrap();
+        assert_eq!(regex.match_at(&lines, pos(0, 0)), Err(RegexExhausted));
+    }
+
+    #[test]
+    fn long_pattern() {
+        // Must not overflow the stack
+        let line = "x".repeat(1_000);
+        for atom in [r"x*", r"\%(x\)"] {
+            let regex = Regex::new(&atom.repeat(50)).unwrap();
+            assert!(regex
+                .match_at(&[line.as_str()], pos(0, 0))
+                .unwrap()
+                .is_some());
+            let regex = Regex::new(&atom.repeat(1_000)).unwrap();
+            assert_eq!(
+                regex.match_at(&[line.as_str()], pos(0, 0)),
+                Err(RegexExhausted)
+            );
+        }
+    }
+
+    #[test]
+    fn long_literal() {
+        let line = "x".repeat(100_000);
+        let regex = Regex::new(&line).unwrap();
+        let found = regex
+            .find_from(&[line.as_str()], pos(0, 0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.end, pos(0, line.len()));
+    }
+
+    #[test]
+    fn find_iter_line() {
+        let regex = Regex::new("a").unwrap();
+        let lines = ["bab a", "aaa"];
+        let starts = regex
+            .find_iter(&lines, 0)
+            .map(|found| found.start)
+            .collect::<Vec<_>>();
+        assert_eq!(starts, vec![pos(0, 1), pos(0, 4)]);
+        assert_eq!(regex.find_iter(&["b", "a"], 0).next(), None);
+
+        let regex = Regex::new("x*").unwrap();
+        let starts = regex
+            .find_iter(&["axxb"], 0)
+            .map(|found| (found.start.index, found.end.index))
+            .collect::<Vec<_>>();
+        assert_eq!(starts, vec![(0, 0), (1, 3), (4, 4)]);
+    }
+
+    #[test]
+    fn find_iter_many_lines() {
+        // Each line is searched alone, so this is fast
+        let lines = vec![String::from("b"); 20_000];
+        let regex = Regex::new("a").unwrap();
+        for line in 0..lines.len() {
+            assert_eq!(regex.find_iter(&lines, line).next(), None);
+        }
+    }
+
+    #[test]
+    fn find_budget() {
+        // The step budget is shared by all starts of a search
+        let lines = vec!["x".repeat(80); 20_000];
+        let regex = Regex::new(r"\_.\{-}y").unwrap();
+        assert_eq!(regex.find_from(&lines, pos(0, 0)), Err(RegexExhausted));
+        assert_eq!(
+            regex.find_before(&lines, pos(19_999, 80)),
+            Err(RegexExhausted)
+        );
+
+        // The budget grows with each start, so a literal is found at the end of a long
+        // buffer
+        let mut lines = vec![String::from("hay stack"); 150_000];
+        lines.push(String::from("a needle"));
+        let regex = Regex::new("needle").unwrap();
+        let found = regex.find_from(&lines, pos(0, 0)).unwrap().unwrap();
+        assert_eq!(found.start, pos(150_000, 2));
+        let found = regex.find_before(&lines[..], pos(150_001, 0)).unwrap();
+        assert_eq!(found.map(|found| found.start), Some(pos(150_000, 2)));
+        assert_eq!(regex.find_from(&lines[..150_000], pos(0, 0)), Ok(None));
+    }
+
+    #[test]
+    fn find_multiline() {
+        let regex = Regex::new(r"b\nc").unwrap();
+        let lines = ["a", "ab", "cd"];
+        let found = regex.find_from(&lines, pos(0, 0)).unwrap().unwrap();
+        assert_eq!((found.start, found.end), (pos(1, 1), pos(2, 1)));
+        let found = regex.find_before(&lines, pos(2, 0)).unwrap().unwrap();
+        assert_eq!(found.start, pos(1, 1));
+    }
+}
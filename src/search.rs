@@ -0,0 +1,197 @@
+use alloc::{boxed::Box, string::String};
+
+/// Where to place the cursor relative to a search match
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SearchOffset {
+    /// Lines below (or above if negative) the match, in column 1. This is linewise.
+    Lines(isize),
+    /// Characters right (or left if negative) of the start of the match
+    Start(isize),
+    /// Characters right (or left if negative) of the end of the match. This is inclusive.
+    End(isize),
+}
+
+impl SearchOffset {
+    /// Parse an offset like `e+1`, `s-2`, `b`, `+3`, or `-`, returns None if invalid
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut chars = value.chars();
+        let (kind, count): (fn(isize) -> Self, &str) = match chars.next() {
+            None => return Some(Self::default()),
+            Some('e') => (Self::End, chars.as_str()),
+            Some('s' | 'b') => (Self::Start, chars.as_str()),
+            Some(_) => (Self::Lines, value),
+        };
+
+        let (negative, digits) = match count.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, count.strip_prefix('+').unwrap_or(count)),
+        };
+        let number = if digits.is_empty() {
+            // A sign without a number means one, no sign means zero
+            isize::from(count != digits)
+        } else {
+            digits.parse::<isize>().ok()?
+        };
+        Some(kind(if negative {
+            number.saturating_neg()
+        } else {
+            number
+        }))
+    }
+
+    /// Returns true if the search is linewise when used with an operator
+    pub fn linewise(&self) -> bool {
+        matches!(self, Self::Lines(_))
+    }
+
+    /// Returns true if the match end is included when used with an operator
+    pub fn inclusive(&self) -> bool {
+        matches!(self, Self::End(_))
+    }
+}
+
+impl Default for SearchOffset {
+    fn default() -> Self {
+        Self::Start(0)
+    }
+}
+
+/// A parsed search command like `/pattern/e+1;?other`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SearchRequest {
+    /// Search pattern, empty to use the last pattern
+    pub pattern: String,
+    /// Search forwards if true, backwards if false
+    pub forwards: bool,
+    /// Cursor placement relative to the match
+    pub offset: SearchOffset,
+    /// Search to perform from the match of this search, after `;`
+    pub next: Option<Box<SearchRequest>>,
+}
+
+impl SearchRequest {
+    /// Parse the text typed after `/` (if forwards) or `?` (if backwards). Returns None
+    /// if an offset is invalid or `;` is not followed by another search, like vim's
+    /// errors for these.
+    pub fn parse(value: &str, forwards: bool) -> Option<Self> {
+        let separator = if forwards { '/' } else { '?' };
+
+        // Find unescaped separator
+        let mut end_opt = None;
+        let mut escaped = false;
+        for (i, c) in value.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == separator {
+                end_opt = Some(i);
+                break;
+            }
+        }
+
+        let (pattern, rest) = match end_opt {
+            Some(end) => (
+                value.get(..end).unwrap_or(value),
+                value.get(end.saturating_add(1)..).unwrap_or(""),
+            ),
+            None => (value, ""),
+        };
+
+        // Split offset from the next search
+        let (offset, next) = match rest.split_once(';') {
+            Some((offset, next)) => {
+                let mut chars = next.chars();
+                let next = match chars.next() {
+                    Some('/') => Self::parse(chars.as_str(), true)?,
+                    Some('?') => Self::parse(chars.as_str(), false)?,
+                    _ => return None,
+                };
+                (offset, Some(Box::new(next)))
+            }
+            None => (rest, None),
+        };
+
+        Some(Self {
+            pattern: pattern.into(),
+            forwards,
+            offset: SearchOffset::parse(offset)?,
+            next,
+        })
+    }
+
+    /// Returns true if the search is linewise when used with an operator,
+    /// determined by the last search in the chain
+    pub fn linewise(&self) -> bool {
+        match &self.next {
+            Some(next) => next.linewise(),
+            None => self.offset.linewise(),
+        }
+    }
+
+    /// Returns true if the match end is included when used with an operator,
+    /// determined by the last search in the chain
+    pub fn inclusive(&self) -> bool {
+        match &self.next {
+            Some(next) => next.inclusive(),
+            None => self.offset.inclusive(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset() {
+        assert_eq!(SearchOffset::parse(""), Some(SearchOffset::Start(0)));
+        assert_eq!(SearchOffset::parse("e+1"), Some(SearchOffset::End(1)));
+        assert_eq!(SearchOffset::parse("e"), Some(SearchOffset::End(0)));
+        assert_eq!(SearchOffset::parse("s-2"), Some(SearchOffset::Start(-2)));
+        assert_eq!(SearchOffset::parse("b"), Some(SearchOffset::Start(0)));
+        assert_eq!(SearchOffset::parse("b+"), Some(SearchOffset::Start(1)));
+        assert_eq!(SearchOffset::parse("3"), Some(SearchOffset::Lines(3)));
+        assert_eq!(SearchOffset::parse("+3"), Some(SearchOffset::Lines(3)));
+        assert_eq!(SearchOffset::parse("-"), Some(SearchOffset::Lines(-1)));
+        assert_eq!(SearchOffset::parse("x"), None);
+        assert_eq!(SearchOffset::parse("e+x"), None);
+    }
+
+    #[test]
+    fn request() {
+        let request = SearchRequest::parse("foo/e+1", true).unwrap();
+        assert_eq!(request.pattern, "foo");
+        assert_eq!(request.offset, SearchOffset::End(1));
+        assert!(request.inclusive() && !request.linewise());
+
+        // The separator can be escaped, and the other separator is part of the pattern
+        let request = SearchRequest::parse(r"a\?b?2", false).unwrap();
+        assert_eq!(request.pattern, r"a\?b");
+        assert_eq!(request.offset, SearchOffset::Lines(2));
+        assert!(request.linewise());
+        let request = SearchRequest::parse("a?b", true).unwrap();
+        assert_eq!(request.pattern, "a?b");
+        assert_eq!(request.offset, SearchOffset::default());
+
+        let request = SearchRequest::parse("a/;/b/e", true).unwrap();
+        assert_eq!(request.pattern, "a");
+        assert_eq!(request.offset, SearchOffset::default());
+        let next = request.next.as_deref().unwrap();
+        assert_eq!(next.pattern, "b");
+        assert!(next.forwards);
+        assert_eq!(next.offset, SearchOffset::End(0));
+        // The last search in the chain decides how operators use it
+        assert!(request.inclusive());
+        let next = SearchRequest::parse("a/s-2;?b", true)
+            .unwrap()
+            .next
+            .unwrap();
+        assert_eq!((next.pattern.as_str(), next.forwards), ("b", false));
+
+        assert_eq!(SearchRequest::parse("foo/x", true), None);
+        assert_eq!(SearchRequest::parse("a/;b", true), None);
+        assert_eq!(SearchRequest::parse("a/;/b/x", true), None);
+    }
+}
@@ -0,0 +1,574 @@
+//! Decoder for terminal input, turning bytes into keys
+//!
+//! Supports UTF-8 text, control characters, Alt as an Escape prefix, CSI and SS3 sequences
+//! with xterm modifiers, xterm `modifyOtherKeys`, the kitty keyboard protocol, SGR mouse
+//! reports, and bracketed paste. Mouse positions are screen cells, which hosts convert to
+//! positions in the buffer.
+
+use alloc::{string::String, vec::Vec};
+use core::str;
+
+use crate::{Key, KeyCode, Modifiers, Mouse, MouseAction, MouseButton};
+
+/// Longest escape sequence that is buffered, longer sequences are discarded
+pub const TERMINAL_MAX_SEQUENCE_LEN: usize = 64;
+
+/// Sequence ending bracketed paste
+const PASTE_END: &[u8] = b"\x1B[201~";
+
+/// Input decoded from a terminal
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TerminalInput {
+    Key(Key),
+    /// Text from bracketed paste, which should be handled with [`crate::Parser::paste`].
+    /// Line breaks are `\n`.
+    Paste(String),
+}
+
+/// Result of decoding the start of the input
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Decoded {
+    /// Key and the number of bytes used
+    Key(Key, usize),
+    /// Number of bytes used by a sequence that is not a key
+    Skip(usize),
+    /// Number of bytes used by the sequence starting bracketed paste
+    PasteStart(usize),
+    /// More bytes are needed
+    Incomplete,
+}
+
+impl Decoded {
+    /// Add modifiers to the decoded key, and count a prefix of len bytes
+    fn prefixed(self, modifiers: Modifiers, len: usize) -> Self {
+        match self {
+            Self::Key(mut key, used) => {
+                key.modifiers.insert(modifiers);
+                Self::Key(key, used.saturating_add(len))
+            }
+            Self::Skip(used) => Self::Skip(used.saturating_add(len)),
+            Self::PasteStart(used) => Self::PasteStart(used.saturating_add(len)),
+            Self::Incomplete => Self::Incomplete,
+        }
+    }
+}
+
+/// Key with no modifiers
+fn key(code: KeyCode) -> Key {
+    Key::from(code)
+}
+
+/// Modifiers from an xterm or kitty modifier parameter, which is one more than a bit mask
+fn modifiers(param: Option<u32>) -> Modifiers {
+    let bits = param.unwrap_or(1).saturating_sub(1);
+    let mut modifiers = Modifiers::NONE;
+    for (bit, modifier) in [
+        (1, Modifiers::SHIFT),
+        (2, Modifiers::ALT),
+        (4, Modifiers::CTRL),
+        (8, Modifiers::SUPER),
+        // Meta in the kitty protocol
+        (32, Modifiers::ALT),
+    ] {
+        if bits & bit != 0 {
+            modifiers.insert(modifier);
+        }
+    }
+    modifiers
+}
+
+/// Parameter of a CSI sequence, like the 5 in `1;5A`, with a sub parameter index for
+/// parameters like `97:65`
+fn param(params: &str, index: usize, sub: usize) -> Option<u32> {
+    params
+        .split(';')
+        .nth(index)?
+        .split(':')
+        .nth(sub)?
+        .parse()
+        .ok()
+}
+
+/// Key for a control character
+fn control(byte: u8) -> Key {
+    let ctrl = |c: u8| Key::new(KeyCode::Char(char::from(c)), Modifiers::CTRL);
+    match byte {
+        b'\t' => key(KeyCode::Tab),
+        b'\r' => key(KeyCode::Enter),
+        0x7F => key(KeyCode::Backspace),
+        // Ctrl-Space and Ctrl-@ both send NUL
+        0x00 => ctrl(b' '),
+        // Ctrl with a letter, including Ctrl-H and Ctrl-J
+        0x01..=0x1A => ctrl(byte.saturating_add(0x60)),
+        // Ctrl-\, Ctrl-], Ctrl-^, and Ctrl-_
+        _ => ctrl(byte.saturating_add(0x40)),
+    }
+}
+
+/// Key code for a key number in `CSI number ~`
+fn tilde_code(number: u32) -> Option<KeyCode> {
+    Some(match number {
+        1 | 7 => KeyCode::Home,
+        2 => KeyCode::Insert,
+        3 => KeyCode::Delete,
+        4 | 8 => KeyCode::End,
+        5 => KeyCode::PageUp,
+        6 => KeyCode::PageDown,
+        11..=15 => KeyCode::F(u8::try_from(number.saturating_sub(10)).ok()?),
+        17..=21 => KeyCode::F(u8::try_from(number.saturating_sub(11)).ok()?),
+        23..=26 => KeyCode::F(u8::try_from(number.saturating_sub(12)).ok()?),
+        28 | 29 => KeyCode::F(u8::try_from(number.saturating_sub(13)).ok()?),
+        31..=34 => KeyCode::F(u8::try_from(number.saturating_sub(14)).ok()?),
+        _ => return None,
+    })
+}
+
+/// Key code for a final character of a CSI or SS3 sequence, like `A` for Up
+fn letter_code(c: u8) -> Option<KeyCode> {
+    Some(match c {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'E' => KeyCode::KeypadBegin,
+        b'F' => KeyCode::End,
+        b'H' => KeyCode::Home,
+        b'P' => KeyCode::F(1),
+        b'Q' => KeyCode::F(2),
+        b'R' => KeyCode::F(3),
+        b'S' => KeyCode::F(4),
+        _ => return None,
+    })
+}
+
+/// Key code for a keypad key in application mode, like `ESC O p` for 0
+fn keypad_code(c: u8) -> Option<KeyCode> {
+    Some(KeyCode::Keypad(match c {
+        b'M' => '\n',
+        b'X' => '=',
+        b'j' => '*',
+        b'k' => '+',
+        b'l' => ',',
+        b'm' => '-',
+        b'n' => '.',
+        b'o' => '/',
+        b'p'..=b'y' => char::from(c.saturating_sub(b'p').saturating_add(b'0')),
+        _ => return None,
+    }))
+}
+
+/// Key code for a unicode key number in `CSI number u` and `CSI 27 ; modifiers ; number ~`,
+/// including the private use numbers the kitty protocol uses for other keys
+fn unicode_code(number: u32) -> Option<KeyCode> {
+    Some(match number {
+        8 | 127 => KeyCode::Backspace,
+        9 => KeyCode::Tab,
+        13 => KeyCode::Enter,
+        27 => KeyCode::Escape,
+        57361 => KeyCode::PrintScreen,
+        57362 => KeyCode::Pause,
+        57363 => KeyCode::Menu,
+        57376..=57387 => KeyCode::F(u8::try_from(number.saturating_sub(57363)).ok()?),
+        57399..=57408 => KeyCode::Keypad(char::from_digit(number.saturating_sub(57399), 10)?),
+        57409 => KeyCode::Keypad('.'),
+        57410 => KeyCode::Keypad('/'),
+        57411 => KeyCode::Keypad('*'),
+        57412 => KeyCode::Keypad('-'),
+        57413 => KeyCode::Keypad('+'),
+        57414 => KeyCode::Keypad('\n'),
+        57415 => KeyCode::Keypad('='),
+        57416 => KeyCode::Keypad(','),
+        57417 => KeyCode::Left,
+        57418 => KeyCode::Right,
+        57419 => KeyCode::Up,
+        57420 => KeyCode::Down,
+        57421 => KeyCode::PageUp,
+        57422 => KeyCode::PageDown,
+        57423 => KeyCode::Home,
+        57424 => KeyCode::End,
+        57425 => KeyCode::Insert,
+        57426 => KeyCode::Delete,
+        57427 => KeyCode::KeypadBegin,
+        // Other private use numbers are lock, media, and modifier keys
+        57344..=63743 => return None,
+        _ => KeyCode::Char(char::from_u32(number)?),
+    })
+}
+
+/// Key for a unicode key number with modifiers, used by the kitty protocol and
+/// `modifyOtherKeys`. The shifted key number is used if it was reported.
+fn unicode_key(number: u32, shifted: Option<u32>, modifiers: Modifiers) -> Option<Key> {
+    let mut code = unicode_code(shifted.unwrap_or(number))?;
+    if let KeyCode::Char(c) = code {
+        if modifiers.contains(Modifiers::SHIFT) && shifted.is_none() {
+            code = KeyCode::Char(c.to_ascii_uppercase());
+        }
+    }
+    Some(Key::new(code, modifiers))
+}
+
+/// Key for an SGR mouse report, like `0;10;5` from `CSI < 0 ; 10 ; 5 M`. The position is
+/// the screen cell, starting at 0, which hosts convert to a position in the buffer.
+fn mouse_key(params: &str, release: bool) -> Option<Key> {
+    let code = param(params, 0, 0)?;
+    let column = param(params, 1, 0)?.saturating_sub(1);
+    let row = param(params, 2, 0)?.saturating_sub(1);
+    let button = match code & 0b11 {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Middle),
+        2 => Some(MouseButton::Right),
+        _ => None,
+    };
+    let action = if code & 64 != 0 {
+        match code & 0b11 {
+            0 => MouseAction::ScrollUp,
+            1 => MouseAction::ScrollDown,
+            2 => MouseAction::ScrollLeft,
+            _ => MouseAction::ScrollRight,
+        }
+    } else if code & 128 != 0 {
+        // Extra buttons are not supported
+        return None;
+    } else if code & 32 != 0 {
+        // Motion without a button held is not a key
+        MouseAction::Drag(button?)
+    } else if release {
+        MouseAction::Release(button?)
+    } else {
+        MouseAction::Press {
+            button: button?,
+            clicks: 1,
+        }
+    };
+    let mut modifiers = Modifiers::NONE;
+    for (bit, modifier) in [
+        (4, Modifiers::SHIFT),
+        (8, Modifiers::ALT),
+        (16, Modifiers::CTRL),
+    ] {
+        if code & bit != 0 {
+            modifiers.insert(modifier);
+        }
+    }
+    let mouse = Mouse {
+        action,
+        line: row as usize,
+        index: column as usize,
+    };
+    Some(Key::new(KeyCode::Mouse(mouse), modifiers))
+}
+
+/// Decode a CSI sequence, starting after `ESC [`
+fn decode_csi(bytes: &[u8], finish: bool) -> Decoded {
+    // Find the final byte, after parameter and intermediate bytes
+    let mut end = 0;
+    let last = loop {
+        match bytes.get(end) {
+            Some(0x20..=0x3F) => end = end.saturating_add(1),
+            Some(&last @ 0x40..=0x7E) => break last,
+            // Invalid sequence, skip up to the invalid byte
+            Some(_) => return Decoded::Skip(end),
+            None if finish || end >= TERMINAL_MAX_SEQUENCE_LEN => return Decoded::Skip(end),
+            None => return Decoded::Incomplete,
+        }
+    };
+    let len = end.saturating_add(1);
+    let params = bytes
+        .get(..end)
+        .and_then(|params| str::from_utf8(params).ok())
+        .unwrap_or("");
+
+    // Ignore key release events from the kitty protocol
+    if param(params, 1, 1) == Some(3) {
+        return Decoded::Skip(len);
+    }
+    let modifiers = modifiers(param(params, 1, 0));
+    let key = match last {
+        // X10 mouse reports are followed by three bytes
+        b'M' if params.is_empty() => {
+            let len = len.saturating_add(3);
+            return if bytes.len() >= len || finish {
+                Decoded::Skip(len.min(bytes.len()))
+            } else {
+                Decoded::Incomplete
+            };
+        }
+        // Linux console function keys, like `ESC [ [ A` for F1
+        b'[' if params.is_empty() => {
+            return match bytes.get(len) {
+                Some(&c @ b'A'..=b'E') => Decoded::Key(
+                    key(KeyCode::F(c.saturating_sub(b'A').saturating_add(1))),
+                    len.saturating_add(1),
+                ),
+                Some(_) => Decoded::Skip(len),
+                None if finish => Decoded::Skip(len),
+                None => Decoded::Incomplete,
+            };
+        }
+        b'Z' => Some(Key::new(KeyCode::Tab, modifiers.union(Modifiers::SHIFT))),
+        b'~' => match param(params, 0, 0) {
+            // modifyOtherKeys, like `CSI 27 ; 5 ; 105 ~` for Ctrl-i
+            Some(27) => param(params, 2, 0).and_then(|number| unicode_key(number, None, modifiers)),
+            // Bracketed paste, the end is found while pasting
+            Some(200) => return Decoded::PasteStart(len),
+            Some(number) => tilde_code(number).map(|code| Key::new(code, modifiers)),
+            None => None,
+        },
+        // Kitty keyboard protocol, like `CSI 97 ; 5 u` for Ctrl-a
+        b'u' => param(params, 0, 0)
+            .and_then(|number| unicode_key(number, param(params, 0, 1), modifiers)),
+        // SGR mouse reports, released buttons end with `m`
+        b'M' | b'm' if params.starts_with('<') => {
+            mouse_key(params.get(1..).unwrap_or(""), last == b'm')
+        }
+        // Keys have no number or 1 before the modifiers, which is not a cursor position report
+        _ if !params.starts_with(['<', '>', '?', '='])
+            && param(params, 0, 0).is_none_or(|number| number == 1) =>
+        {
+            letter_code(last).map(|code| Key::new(code, modifiers))
+        }
+        // Mouse, focus, and other reports
+        _ => None,
+    };
+    match key {
+        Some(key) => Decoded::Key(key, len),
+        None => Decoded::Skip(len),
+    }
+}
+
+/// Decode an SS3 sequence, starting after `ESC O`
+fn decode_ss3(bytes: &[u8], finish: bool) -> Decoded {
+    // Some terminals send modifiers before the final byte, like `ESC O 5 P`
+    let digits = bytes
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    let Some(&last) = bytes.get(digits) else {
+        return if finish || digits >= TERMINAL_MAX_SEQUENCE_LEN {
+            Decoded::Skip(digits)
+        } else {
+            Decoded::Incomplete
+        };
+    };
+    let modifiers = modifiers(
+        bytes
+            .get(..digits)
+            .and_then(|digits| str::from_utf8(digits).ok())
+            .and_then(|digits| digits.parse().ok()),
+    );
+    let len = digits.saturating_add(1);
+    match letter_code(last).or_else(|| keypad_code(last)) {
+        Some(code) => Decoded::Key(Key::new(code, modifiers), len),
+        None => Decoded::Skip(len),
+    }
+}
+
+/// Decode a UTF-8 character
+fn decode_utf8(bytes: &[u8], finish: bool) -> Decoded {
+    let Some(&first) = bytes.first() else {
+        return Decoded::Incomplete;
+    };
+    let len = match first {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => 1,
+    };
+    let Some(encoded) = bytes.get(..len) else {
+        return if finish {
+            Decoded::Key(key(KeyCode::Char(char::REPLACEMENT_CHARACTER)), bytes.len())
+        } else {
+            Decoded::Incomplete
+        };
+    };
+    match str::from_utf8(encoded).ok().and_then(|s| s.chars().next()) {
+        Some(c) => Decoded::Key(key(KeyCode::Char(c)), len),
+        // Invalid bytes are replaced one at a time
+        None => Decoded::Key(key(KeyCode::Char(char::REPLACEMENT_CHARACTER)), 1),
+    }
+}
+
+/// Decode the first key of bytes. If finish is true, no more bytes will arrive, so
+/// incomplete sequences are decoded as well as possible.
+fn decode(bytes: &[u8], finish: bool) -> Decoded {
+    let rest = |start: usize| bytes.get(start..).unwrap_or(&[]);
+    match bytes.first() {
+        None => Decoded::Incomplete,
+        Some(0x1B) => match bytes.get(1) {
+            None if finish => Decoded::Key(key(KeyCode::Escape), 1),
+            None => Decoded::Incomplete,
+            Some(&introducer @ (b'[' | b'O')) => {
+                let decoded = if introducer == b'[' {
+                    decode_csi(rest(2), finish)
+                } else {
+                    decode_ss3(rest(2), finish)
+                };
+                match decoded {
+                    // Escape followed by `[` or `O` is Alt with that character
+                    Decoded::Skip(0) => Decoded::Key(
+                        Key::new(KeyCode::Char(char::from(introducer)), Modifiers::ALT),
+                        2,
+                    ),
+                    decoded => decoded.prefixed(Modifiers::NONE, 2),
+                }
+            }
+            // Escape before a sequence adds Alt, like `ESC ESC [ A` for Alt-Up
+            Some(0x1B) => match bytes.get(2) {
+                Some(b'[' | b'O') => match decode(rest(1), finish) {
+                    Decoded::Key(key, used) if used > 2 => {
+                        Decoded::Key(key, used).prefixed(Modifiers::ALT, 1)
+                    }
+                    Decoded::Incomplete if !finish => Decoded::Incomplete,
+                    _ => Decoded::Key(key(KeyCode::Escape), 1),
+                },
+                None if !finish => Decoded::Incomplete,
+                _ => Decoded::Key(key(KeyCode::Escape), 1),
+            },
+            // Escape before a character is Alt with the character
+            Some(_) => decode(rest(1), finish).prefixed(Modifiers::ALT, 1),
+        },
+        Some(&byte @ (0x00..=0x1F | 0x7F)) => Decoded::Key(control(byte), 1),
+        Some(_) => decode_utf8(bytes, finish),
+    }
+}
+
+/// Decodes bytes read from a terminal into keys and pasted text, buffering incomplete
+/// sequences
+#[derive(Debug, Default)]
+pub struct TerminalDecoder {
+    buffer: Vec<u8>,
+    /// True after the start of bracketed paste, until its end
+    pasting: bool,
+    /// Number of buffered pasted bytes that cannot contain the start of the paste end
+    paste_scanned: usize,
+}
+
+impl TerminalDecoder {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            pasting: false,
+            paste_scanned: 0,
+        }
+    }
+
+    /// Returns true if bytes of an incomplete sequence are buffered, or a paste has not
+    /// ended. Hosts should call [`TerminalDecoder::flush`] if no more bytes arrive soon,
+    /// so that a lone Escape is reported.
+    pub fn pending(&self) -> bool {
+        !self.buffer.is_empty() || self.pasting
+    }
+
+    /// Decode bytes, calling callback with each complete key or paste
+    pub fn decode<F: FnMut(TerminalInput)>(&mut self, bytes: &[u8], callback: F) {
+        self.buffer.extend_from_slice(bytes);
+        self.run(false, callback);
+    }
+
+    /// Decode buffered bytes, assuming no more bytes will arrive to complete them. Pasted
+    /// text is kept until the paste ends, as it can arrive slowly.
+    pub fn flush<F: FnMut(TerminalInput)>(&mut self, callback: F) {
+        self.run(true, callback);
+    }
+
+    fn run<F: FnMut(TerminalInput)>(&mut self, finish: bool, mut callback: F) {
+        let mut start = 0;
+        while let Some(bytes) = self.buffer.get(start..).filter(|bytes| !bytes.is_empty()) {
+            if self.pasting {
+                // Pasted text is not decoded, so it cannot run commands
+                let scanned = self.paste_scanned.min(bytes.len());
+                let Some(end) = bytes
+                    .get(scanned..)
+                    .unwrap_or(&[])
+                    .windows(PASTE_END.len())
+                    .position(|window| window == PASTE_END)
+                else {
+                    // Only search the new bytes and the end of the old ones next time
+                    self.paste_scanned = bytes
+                        .len()
+                        .saturating_sub(PASTE_END.len().saturating_sub(1));
+                    break;
+                };
+                let end = scanned.saturating_add(end);
+                self.paste_scanned = 0;
+                let text = String::from_utf8_lossy(bytes.get(..end).unwrap_or(&[]));
+                // Terminals send line breaks as carriage returns
+                let text = text.replace("\r\n", "\n").replace('\r', "\n");
+                callback(TerminalInput::Paste(text));
+                self.pasting = false;
+                start = start.saturating_add(end).saturating_add(PASTE_END.len());
+                continue;
+            }
+            let used = match decode(bytes, finish) {
+                Decoded::Key(key, used) => {
+                    callback(TerminalInput::Key(key));
+                    used
+                }
+                Decoded::Skip(used) => used,
+                Decoded::PasteStart(used) => {
+                    self.pasting = true;
+                    used
+                }
+                Decoded::Incomplete => break,
+            };
+            // Always make progress, even on an empty invalid sequence
+            start = start.saturating_add(used.max(1));
+        }
+        self.buffer.drain(..start.min(self.buffer.len()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec, vec::Vec};
+
+    use super::*;
+
+    fn decode_chunks(chunks: &[&[u8]]) -> Vec<TerminalInput> {
+        let mut decoder = TerminalDecoder::new();
+        let mut inputs = Vec::new();
+        for chunk in chunks {
+            decoder.decode(chunk, |input| inputs.push(input));
+        }
+        inputs
+    }
+
+    #[test]
+    fn alt_sequence_split() {
+        assert_eq!(
+            decode_chunks(&[b"\x1B\x1B[", b"A"]),
+            vec![TerminalInput::Key(Key::new(KeyCode::Up, Modifiers::ALT))]
+        );
+
+        let mut decoder = TerminalDecoder::new();
+        let mut inputs = Vec::new();
+        decoder.decode(b"\x1B\x1B[", |input| inputs.push(input));
+        assert!(inputs.is_empty() && decoder.pending());
+        decoder.flush(|input| inputs.push(input));
+        assert_eq!(
+            inputs,
+            vec![
+                TerminalInput::Key(key(KeyCode::Escape)),
+                TerminalInput::Key(Key::new(KeyCode::Char('['), Modifiers::ALT)),
+            ]
+        );
+    }
+
+    #[test]
+    fn paste_split() {
+        assert_eq!(
+            decode_chunks(&[b"\x1B[200~a\rb", b"c\x1B[2", b"01~", b"x"]),
+            vec![
+                TerminalInput::Paste("a\nbc".to_string()),
+                TerminalInput::Key(key(KeyCode::Char('x'))),
+            ]
+        );
+
+        let mut chunks: Vec<&[u8]> = vec![b"\x1B[200~"];
+        chunks.extend(b"paste\x1B[201~".chunks(1));
+        assert_eq!(
+            decode_chunks(&chunks),
+            vec![TerminalInput::Paste("paste".to_string())]
+        );
+    }
+}
@@ -1,10 +1,27 @@
 use alloc::{string::String, vec::Vec};
 use core::{fmt, mem};
 
-use crate::{Event, Key, Motion, Operator, Parser, TextObject, Word};
+use crate::{
+    BufferCommand, Completion, Composition, Digraphs, Event, ExCommand, History, Key, KeyCode,
+    Langmap, Modifiers, Motion, Mouse, MouseAction, MouseButton, Operator, Parser, ScrollPosition,
+    SearchRequest, TabPageCommand, TextObject, WindowCommand, WindowFocus, WindowResize, Word,
+    WordChar, WriteRequest,
+};
 
 pub const VI_DEFAULT_REGISTER: char = '"';
 
+/// Character used for [`ViMode::Extra`] while waiting for a window command after Ctrl-W
+pub const VI_WINDOW_EXTRA: char = '\x17';
+
+/// Register put with the middle mouse button, like vim's selection register
+pub const VI_MOUSE_REGISTER: char = '*';
+
+/// Lines scrolled by the mouse wheel, like vim's default `mousescroll`
+pub const VI_MOUSE_SCROLL_LINES: usize = 3;
+
+/// Columns scrolled by the horizontal mouse wheel, like vim's default `mousescroll`
+pub const VI_MOUSE_SCROLL_COLUMNS: usize = 6;
+
 #[derive(Debug)]
 pub struct ViContext<F: FnMut(Event)> {
     callback: F,
@@ -28,9 +45,8 @@ impl<F: FnMut(Event)> ViContext<F> {
     }
 
     fn e(&mut self, event: Event) {
-        match &mut self.pending_change {
-            Some(change) => change.push(event.clone()),
-            None => {}
+        if let Some(change) = &mut self.pending_change {
+            change.push(event.clone());
         }
         (self.callback)(event);
     }
@@ -43,6 +59,9 @@ pub struct ViCmd {
     operator: Option<Operator>,
     motion: Option<Motion>,
     text_object: Option<TextObject>,
+    linewise: bool,
+    /// True if the character at the end of the motion is included, like `d/foo/e`
+    inclusive: bool,
 }
 
 impl fmt::Display for ViCmd {
@@ -96,7 +115,7 @@ impl ViCmd {
         text_object: TextObject,
         ctx: &mut ViContext<F>,
     ) -> bool {
-        if !self.motion.map_or(false, |motion| motion.text_object()) {
+        if !self.motion.is_some_and(|motion| motion.text_object()) {
             // Did not need text object
             return false;
         }
@@ -128,6 +147,8 @@ impl ViCmd {
         let count = self.count.take().unwrap_or(1);
         let motion = self.motion.take().unwrap_or(Motion::Selection);
         let text_object = self.text_object.take();
+        let linewise = mem::take(&mut self.linewise);
+        let inclusive = mem::take(&mut self.inclusive);
 
         //TODO: clean up logic of Motion, such that actual motions and references to
         // text objects and selections are not in the same enum
@@ -136,23 +157,31 @@ impl ViCmd {
                 ctx.start_change();
 
                 match motion {
-                    Motion::Around => ctx.e(Event::SelectTextObject(
-                        text_object.expect("no text object"),
-                        true,
-                    )),
-                    Motion::Inside => ctx.e(Event::SelectTextObject(
-                        text_object.expect("no text object"),
-                        false,
-                    )),
+                    Motion::Around | Motion::Inside => {
+                        if let Some(text_object) = text_object {
+                            ctx.e(Event::SelectTextObject(
+                                text_object,
+                                motion == Motion::Around,
+                            ));
+                        }
+                    }
                     Motion::Line => {
                         ctx.e(Event::SelectLineStart);
                     }
                     Motion::Selection => {}
                     _ => {
-                        ctx.e(Event::SelectStart);
+                        if linewise {
+                            ctx.e(Event::SelectLineStart);
+                        } else {
+                            ctx.e(Event::SelectStart);
+                        }
                         for _ in 0..count {
                             ctx.e(Event::Motion(motion));
                         }
+                        // Motions are exclusive, so select one more character
+                        if inclusive && !linewise {
+                            ctx.e(Event::Motion(Motion::RightInLine));
+                        }
                     }
                 }
 
@@ -170,6 +199,9 @@ impl ViCmd {
                         ctx.e(Event::Yank { register });
                         ctx.e(Event::Delete);
                     }
+                    Operator::Fold => {
+                        ctx.e(Event::FoldCreate);
+                    }
                     Operator::ShiftLeft => {
                         ctx.e(Event::ShiftLeft);
                     }
@@ -193,14 +225,14 @@ impl ViCmd {
                 }
             }
             None => match motion {
-                Motion::Around => ctx.e(Event::SelectTextObject(
-                    text_object.expect("no text object"),
-                    true,
-                )),
-                Motion::Inside => ctx.e(Event::SelectTextObject(
-                    text_object.expect("no text object"),
-                    false,
-                )),
+                Motion::Around | Motion::Inside => {
+                    if let Some(text_object) = text_object {
+                        ctx.e(Event::SelectTextObject(
+                            text_object,
+                            motion == Motion::Around,
+                        ));
+                    }
+                }
                 _ => {
                     for _ in 0..count {
                         ctx.e(Event::Motion(motion));
@@ -213,6 +245,315 @@ impl ViCmd {
     }
 }
 
+/// Byte index of the character before cursor
+fn cmdline_prev(value: &str, cursor: usize) -> Option<usize> {
+    let c = value.get(..cursor)?.chars().next_back()?;
+    cursor.checked_sub(c.len_utf8())
+}
+
+/// Byte index of the character after cursor
+fn cmdline_next(value: &str, cursor: usize) -> Option<usize> {
+    let c = value.get(cursor..)?.chars().next()?;
+    cursor.checked_add(c.len_utf8())
+}
+
+/// Replace command line value while recalling history, placing cursor at the end
+fn cmdline_recall(value: &mut String, cursor: &mut usize, recalled: Option<String>) {
+    if let Some(recalled) = recalled {
+        *value = recalled;
+        *cursor = value.len();
+    }
+}
+
+/// Handle editing keys in command and search modes, returns false if the
+/// command line should be closed
+pub(crate) fn cmdline_key(
+    value: &mut String,
+    cursor: &mut usize,
+    history: &mut History,
+    key: Key,
+) -> bool {
+    // Make sure cursor is valid, in case the value was modified externally
+    if !value.is_char_boundary(*cursor) {
+        *cursor = value.len();
+    }
+
+    match (key.modifiers, key.code) {
+        (Modifiers::NONE, KeyCode::Backspace) | (Modifiers::CTRL, KeyCode::Char('h')) => {
+            if value.is_empty() {
+                return false;
+            }
+            if let Some(prev) = cmdline_prev(value, *cursor) {
+                value.replace_range(prev..*cursor, "");
+                *cursor = prev;
+            }
+            history.reset_recall();
+        }
+        (Modifiers::NONE, KeyCode::Delete) => {
+            // At end of line, delete character before cursor like vim
+            let range = match cmdline_next(value, *cursor) {
+                Some(next) => Some(*cursor..next),
+                None => cmdline_prev(value, *cursor).map(|prev| prev..*cursor),
+            };
+            if let Some(range) = range {
+                *cursor = range.start;
+                value.replace_range(range, "");
+            }
+            history.reset_recall();
+        }
+        (Modifiers::NONE, KeyCode::Left) => {
+            if let Some(prev) = cmdline_prev(value, *cursor) {
+                *cursor = prev;
+            }
+        }
+        (Modifiers::NONE, KeyCode::Right) => {
+            if let Some(next) = cmdline_next(value, *cursor) {
+                *cursor = next;
+            }
+        }
+        (Modifiers::NONE, KeyCode::Home) | (Modifiers::CTRL, KeyCode::Char('b')) => {
+            *cursor = 0;
+        }
+        (Modifiers::NONE, KeyCode::End) | (Modifiers::CTRL, KeyCode::Char('e')) => {
+            *cursor = value.len();
+        }
+        // Recall older entry starting with typed text
+        (Modifiers::NONE, KeyCode::Up) => {
+            cmdline_recall(value, cursor, history.previous(value, true));
+        }
+        // Recall newer entry starting with typed text
+        (Modifiers::NONE, KeyCode::Down) => cmdline_recall(value, cursor, history.next(true)),
+        // Recall older entry without filtering
+        (Modifiers::NONE, KeyCode::PageUp) | (Modifiers::CTRL, KeyCode::Char('p')) => {
+            cmdline_recall(value, cursor, history.previous(value, false));
+        }
+        // Recall newer entry without filtering
+        (Modifiers::NONE, KeyCode::PageDown) | (Modifiers::CTRL, KeyCode::Char('n')) => {
+            cmdline_recall(value, cursor, history.next(false));
+        }
+        // Delete word before cursor
+        (Modifiers::CTRL, KeyCode::Char('w')) => {
+            let mut start = *cursor;
+            let mut last_kind = None;
+            for (i, c) in value.get(..*cursor).unwrap_or("").char_indices().rev() {
+                let kind = WordChar::new(c, Word::Lower);
+                match last_kind {
+                    // Skip blanks before the word
+                    None if kind == WordChar::Blank => {}
+                    None => last_kind = Some(kind),
+                    Some(last_kind) if last_kind != kind => break,
+                    Some(_) => {}
+                }
+                start = i;
+            }
+            value.replace_range(start..*cursor, "");
+            *cursor = start;
+            history.reset_recall();
+        }
+        // Delete all characters before cursor
+        (Modifiers::CTRL, KeyCode::Char('u')) => {
+            value.replace_range(..*cursor, "");
+            *cursor = 0;
+            history.reset_recall();
+        }
+        (Modifiers::NONE, KeyCode::Char(c)) => {
+            value.insert(*cursor, c);
+            *cursor = cursor.saturating_add(c.len_utf8());
+            history.reset_recall();
+        }
+        _ => {
+            //TODO: more keys
+        }
+    }
+    true
+}
+
+/// Insert pasted text in command and search modes. Line breaks cannot be part of the
+/// command line, so only the first line is inserted.
+pub(crate) fn cmdline_paste(
+    value: &mut String,
+    cursor: &mut usize,
+    history: &mut History,
+    text: &str,
+) {
+    // Make sure cursor is valid, in case the value was modified externally
+    if !value.is_char_boundary(*cursor) {
+        *cursor = value.len();
+    }
+
+    let line = text.lines().next().unwrap_or("");
+    value.insert_str(*cursor, line);
+    *cursor = cursor.saturating_add(line.len());
+    history.reset_recall();
+}
+
+/// Preview of a search being typed, which is cancelled if the search is invalid
+fn search_preview(value: &str, forwards: bool) -> Event {
+    match SearchRequest::parse(value, forwards) {
+        Some(request) => Event::SearchPreview(request),
+        None => Event::SearchPreviewCancel,
+    }
+}
+
+/// Event for an ex command switching buffers or tab pages
+fn ex_buffer_event(command: &ExCommand) -> Option<Event> {
+    // Count can be an argument or before the name
+    let number = command.args.parse::<usize>().ok().or(command.count());
+    let path = command.args().map(String::from);
+    let event = if command.is("bNext", 2) || command.is("bprevious", 2) {
+        Event::Buffer(BufferCommand::Previous(number.unwrap_or(1)))
+    } else if command.is("bdelete", 2) {
+        Event::Buffer(BufferCommand::Delete {
+            number,
+            force: command.bang,
+        })
+    } else if command.is("bfirst", 2) || command.is("brewind", 2) {
+        Event::Buffer(BufferCommand::First)
+    } else if command.is("blast", 2) {
+        Event::Buffer(BufferCommand::Last)
+    } else if command.is("bnext", 2) {
+        Event::Buffer(BufferCommand::Next(number.unwrap_or(1)))
+    } else if command.is("buffer", 1) {
+        match (number, command.args()) {
+            (Some(number), _) => Event::Buffer(BufferCommand::Number(number)),
+            (None, Some(name)) => Event::Buffer(BufferCommand::Name(name.into())),
+            (None, None) => return None,
+        }
+    } else if command.is("buffers", 7) || command.is("files", 5) || command.is("ls", 2) {
+        Event::Buffer(BufferCommand::List { all: command.bang })
+    } else if command.is("tabNext", 4) || command.is("tabprevious", 4) {
+        Event::TabPage(TabPageCommand::Previous(number.unwrap_or(1)))
+    } else if command.is("tabclose", 4) {
+        Event::TabPage(TabPageCommand::Close {
+            force: command.bang,
+        })
+    } else if command.is("tabedit", 4) || command.is("tabnew", 6) {
+        Event::TabPage(TabPageCommand::New { path })
+    } else if command.is("tabfirst", 6) || command.is("tabrewind", 4) {
+        Event::TabPage(TabPageCommand::First)
+    } else if command.is("tablast", 4) {
+        Event::TabPage(TabPageCommand::Last)
+    } else if command.is("tabnext", 4) {
+        Event::TabPage(match number {
+            Some(number) => TabPageCommand::Number(number),
+            None => TabPageCommand::Next,
+        })
+    } else if command.is("tabonly", 4) {
+        Event::TabPage(TabPageCommand::Only {
+            force: command.bang,
+        })
+    } else {
+        return None;
+    };
+    Some(event)
+}
+
+/// Run an ex command, returns false if it is not supported
+fn ex_run<F: FnMut(Event)>(
+    command: &ExCommand,
+    digraphs: &mut Digraphs,
+    ctx: &mut ViContext<F>,
+) -> bool {
+    let write = |modified_only, save_as| WriteRequest {
+        range: command.range,
+        path: command.args().map(String::from),
+        force: command.bang,
+        modified_only,
+        save_as,
+    };
+    // Writing to a shell command, like `:w !cmd`, is left to the editor, and must not write
+    // to a file named after the command
+    if command.args.starts_with('!')
+        && [
+            ("saveas", 3),
+            ("update", 2),
+            ("wq", 2),
+            ("write", 1),
+            ("xit", 1),
+        ]
+        .iter()
+        .any(|&(name, min_len)| command.is(name, min_len))
+    {
+        return false;
+    }
+    if command.is("digraphs", 3) {
+        if !digraphs.define(command.args) {
+            log::warn!("invalid digraphs {:?}", command.args);
+        }
+    } else if command.is("close", 3) {
+        ctx.e(Event::Window(WindowCommand::Close {
+            force: command.bang,
+        }));
+    } else if command.is("edit", 1) {
+        ctx.e(Event::Edit {
+            path: command.args().map(String::from),
+            force: command.bang,
+        });
+    } else if command.is("only", 2) {
+        ctx.e(Event::Window(WindowCommand::Only {
+            force: command.bang,
+        }));
+    } else if command.is("quit", 1) {
+        ctx.e(Event::Quit {
+            force: command.bang,
+        });
+    } else if command.is("saveas", 3) {
+        ctx.e(Event::Write(write(false, true)));
+    } else if command.is("split", 2) {
+        ctx.e(Event::Window(WindowCommand::Split {
+            size: command.count(),
+            path: command.args().map(String::from),
+        }));
+    } else if command.is("update", 2) {
+        ctx.e(Event::Write(write(true, false)));
+    } else if command.is("vsplit", 2) {
+        ctx.e(Event::Window(WindowCommand::VerticalSplit {
+            size: command.count(),
+            path: command.args().map(String::from),
+        }));
+    } else if command.is("wq", 2) {
+        ctx.e(Event::WriteQuit(write(false, false)));
+    } else if command.is("write", 1) {
+        ctx.e(Event::Write(write(false, false)));
+    } else if command.is("xit", 1) {
+        ctx.e(Event::WriteQuit(write(true, false)));
+    } else if let Some(event) = ex_buffer_event(command) {
+        ctx.e(event);
+    } else {
+        return false;
+    }
+    true
+}
+
+/// Keys being collected in insert and command modes, used to draw an indicator
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ViPending {
+    /// Waiting for digraph characters after Ctrl-K, with the first character if typed
+    Digraph(Option<char>),
+    /// Waiting for a literal key or character code after Ctrl-V, with the code prefix
+    /// (`x`, `u`, `U`, `o`, or None for decimal), the code so far, and number of digits
+    Literal {
+        prefix: Option<char>,
+        value: Option<u32>,
+        len: u32,
+    },
+}
+
+/// Character inserted for a key typed after Ctrl-V
+fn literal_char(key: Key) -> Option<char> {
+    match (key.modifiers, key.code) {
+        (Modifiers::NONE, KeyCode::Backspace) => Some('\x08'),
+        (Modifiers::NONE, KeyCode::Char(c)) => Some(c),
+        (Modifiers::CTRL, KeyCode::Char('?')) => Some('\x7F'),
+        (Modifiers::CTRL, KeyCode::Char(c)) => char::from_u32(u32::from(c) & 0x1F),
+        (Modifiers::NONE, KeyCode::Delete) => Some('\x7F'),
+        (Modifiers::NONE, KeyCode::Enter) => Some('\r'),
+        (Modifiers::NONE, KeyCode::Escape) => Some('\x1B'),
+        (Modifiers::NONE, KeyCode::Tab) => Some('\t'),
+        _ => None,
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ViMode {
     /// Normal mode
@@ -223,14 +564,20 @@ pub enum ViMode {
     Insert,
     /// Replace mode
     Replace,
+    /// Virtual replace mode, replacing screen cells instead of characters
+    VirtualReplace,
     /// Visual mode
     Visual,
     /// Visual line mode
     VisualLine,
-    /// Command mode
-    Command { value: String },
-    /// Search mode
-    Search { value: String, forwards: bool },
+    /// Command mode, cursor is a byte index into value
+    Command { value: String, cursor: usize },
+    /// Search mode, cursor is a byte index into value
+    Search {
+        value: String,
+        cursor: usize,
+        forwards: bool,
+    },
 }
 
 #[derive(Debug)]
@@ -241,6 +588,19 @@ pub struct ViParser {
     pub semicolon_motion: Option<Motion>,
     pub pending_change: Option<Vec<Event>>,
     pub last_change: Option<Vec<Event>>,
+    pub command_history: History,
+    pub search_history: History,
+    pub completion: Option<Completion>,
+    /// False if completion was requested with Shift-Tab, so the last candidate is selected
+    pub completion_forwards: bool,
+    pub pending: Option<ViPending>,
+    pub digraphs: Digraphs,
+    /// Visual mode to restore with gv
+    pub last_visual_mode: Option<ViMode>,
+    /// Number of replacements in replace mode that can be restored with backspace
+    pub replace_count: usize,
+    /// Translation of command characters, like vim's `langmap`
+    pub langmap: Langmap,
 }
 
 impl ViParser {
@@ -252,21 +612,304 @@ impl ViParser {
             semicolon_motion: None,
             pending_change: None,
             last_change: None,
+            command_history: History::new(),
+            search_history: History::new(),
+            completion: None,
+            completion_forwards: true,
+            pending: None,
+            digraphs: Digraphs::new(),
+            last_visual_mode: None,
+            replace_count: 0,
+            langmap: Langmap::new(),
         }
     }
+
+    /// Insert a character in insert mode, or replace a character in replace modes
+    fn insert_char<F: FnMut(Event)>(&mut self, c: char, ctx: &mut ViContext<F>) {
+        match self.mode {
+            ViMode::Replace => {
+                self.replace_count = self.replace_count.saturating_add(1);
+                ctx.e(Event::ReplaceChar(c));
+            }
+            ViMode::VirtualReplace => {
+                self.replace_count = self.replace_count.saturating_add(1);
+                ctx.e(Event::VirtualReplaceChar(c));
+            }
+            _ => ctx.e(Event::Insert(c)),
+        }
+    }
+
+    /// Leave insert and replace modes without moving the cursor, used before selecting
+    /// with the mouse
+    fn leave_insert<F: FnMut(Event)>(&mut self, ctx: &mut ViContext<F>) {
+        if matches!(
+            self.mode,
+            ViMode::Insert | ViMode::Replace | ViMode::VirtualReplace
+        ) {
+            ctx.finish_change();
+        }
+        self.cmd = ViCmd::default();
+        self.mode = ViMode::Normal;
+    }
+
+    /// Handle mouse keys like vim with `mouse=a`
+    fn mouse<F: FnMut(Event)>(&mut self, mouse: Mouse, ctx: &mut ViContext<F>) {
+        let position = Motion::Position {
+            line: mouse.line,
+            index: mouse.index,
+        };
+        let visual = matches!(self.mode, ViMode::Visual | ViMode::VisualLine);
+        let inserting = matches!(
+            self.mode,
+            ViMode::Insert | ViMode::Replace | ViMode::VirtualReplace
+        );
+        // The wheel scrolls in every mode
+        let scroll = match mouse.action {
+            MouseAction::ScrollUp => Some(Event::ScrollUp(VI_MOUSE_SCROLL_LINES)),
+            MouseAction::ScrollDown => Some(Event::ScrollDown(VI_MOUSE_SCROLL_LINES)),
+            MouseAction::ScrollLeft => Some(Event::ScrollLeft(VI_MOUSE_SCROLL_COLUMNS)),
+            MouseAction::ScrollRight => Some(Event::ScrollRight(VI_MOUSE_SCROLL_COLUMNS)),
+            _ => None,
+        };
+        if let Some(event) = scroll {
+            // Scrolling is not part of a change repeated with .
+            (ctx.callback)(event);
+            return;
+        }
+        // The command line is not part of the buffer
+        if matches!(self.mode, ViMode::Command { .. } | ViMode::Search { .. }) {
+            return;
+        }
+        // Clicking cancels commands waiting for a character
+        if matches!(self.mode, ViMode::Extra(_)) {
+            self.reset();
+        }
+
+        match mouse.action {
+            // Move the cursor, which is also a motion for a pending operator
+            MouseAction::Press {
+                button: MouseButton::Left,
+                clicks: 0 | 1,
+            } => {
+                if visual {
+                    ctx.e(Event::SelectClear);
+                    self.mode = ViMode::Normal;
+                }
+                if inserting {
+                    ViCmd::default().motion(position, ctx);
+                } else {
+                    self.cmd.motion(position, ctx);
+                }
+            }
+            // Select the word with a double click and the line with a triple click
+            MouseAction::Press {
+                button: MouseButton::Left,
+                clicks,
+            } => {
+                self.leave_insert(ctx);
+                if ctx.selection {
+                    ctx.e(Event::SelectClear);
+                }
+                ViCmd::default().motion(position, ctx);
+                if clicks == 2 {
+                    ctx.e(Event::SelectStart);
+                    ctx.e(Event::SelectTextObject(
+                        TextObject::Word(Word::Lower),
+                        false,
+                    ));
+                    self.mode = ViMode::Visual;
+                } else {
+                    ctx.e(Event::SelectLineStart);
+                    self.mode = ViMode::VisualLine;
+                }
+            }
+            // Select from the cursor, which was placed by the press
+            MouseAction::Drag(MouseButton::Left | MouseButton::Right)
+            | MouseAction::Press {
+                button: MouseButton::Right,
+                ..
+            } => {
+                if !visual {
+                    self.leave_insert(ctx);
+                    ctx.e(Event::SelectStart);
+                    self.mode = ViMode::Visual;
+                }
+                ViCmd::default().motion(position, ctx);
+            }
+            // Put the selection register at the click
+            MouseAction::Press {
+                button: MouseButton::Middle,
+                ..
+            } => {
+                if visual {
+                    ctx.e(Event::SelectClear);
+                    self.mode = ViMode::Normal;
+                }
+                self.cmd = ViCmd::default();
+                ViCmd::default().motion(position, ctx);
+                if inserting {
+                    ctx.e(Event::PutAndMove {
+                        register: VI_MOUSE_REGISTER,
+                        after: false,
+                    });
+                } else {
+                    ctx.start_change();
+                    ctx.e(Event::Put {
+                        register: VI_MOUSE_REGISTER,
+                        after: false,
+                    });
+                    ctx.finish_change();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle key while pending, returns a key to handle normally if complete
+    fn pending_key<F: FnMut(Event)>(
+        &mut self,
+        pending: ViPending,
+        key: Key,
+        ctx: &mut ViContext<F>,
+    ) -> Option<Key> {
+        match (pending, key.modifiers, key.code) {
+            (ViPending::Digraph(None), Modifiers::NONE, KeyCode::Char(a)) => {
+                self.pending = Some(ViPending::Digraph(Some(a)));
+                None
+            }
+            (ViPending::Digraph(Some(a)), Modifiers::NONE, KeyCode::Char(b)) => {
+                // Like vim, insert the second character if there is no such digraph
+                Some(Key::from(KeyCode::Char(
+                    self.digraphs.get(a, b).unwrap_or(b),
+                )))
+            }
+            (ViPending::Digraph(_), _, _) => None,
+            (ViPending::Literal { prefix, value, len }, _, _) => {
+                // Like vim, codes without u or U are at most 255
+                let (radix, max_len, max_value) = match prefix {
+                    None => (10, 3, 255),
+                    Some('x' | 'X') => (16, 2, 255),
+                    Some('u') => (16, 4, 0xFFFF),
+                    Some('U') => (16, 8, u32::MAX),
+                    Some(_) => (8, 3, 255),
+                };
+                if let (Modifiers::NONE, KeyCode::Char(c)) = (key.modifiers, key.code) {
+                    if prefix.is_none() && value.is_none() && "xXuUoO".contains(c) {
+                        self.pending = Some(ViPending::Literal {
+                            prefix: Some(c),
+                            value: None,
+                            len: 0,
+                        });
+                        return None;
+                    }
+                    if let Some(digit) = c.to_digit(radix) {
+                        let value = value
+                            .unwrap_or(0)
+                            .saturating_mul(radix)
+                            .saturating_add(digit);
+                        let len = len.saturating_add(1);
+                        if len < max_len {
+                            self.pending = Some(ViPending::Literal {
+                                prefix,
+                                value: Some(value),
+                                len,
+                            });
+                        } else if let Some(c) = char::from_u32(value.min(max_value)) {
+                            self.insert_char(c, ctx);
+                        }
+                        return None;
+                    }
+                }
+
+                match (prefix, value) {
+                    // Insert key literally
+                    (None, None) => {
+                        if let Some(c) = literal_char(key) {
+                            self.insert_char(c, ctx);
+                        }
+                        None
+                    }
+                    // Code ended early, insert it and handle key normally
+                    (_, Some(value)) => {
+                        if let Some(c) = char::from_u32(value.min(max_value)) {
+                            self.insert_char(c, ctx);
+                        }
+                        Some(key)
+                    }
+                    // Prefix without code, insert prefix and handle key normally
+                    (Some(prefix), None) => {
+                        self.insert_char(prefix, ctx);
+                        Some(key)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Save state after parsing a key
+    fn finish<F: FnMut(Event)>(&mut self, ctx: &mut ViContext<F>) {
+        // Reset mode after operators
+        if let Some(mode) = ctx.set_mode.take() {
+            self.mode = mode;
+        }
+
+        // Save change state
+        self.pending_change = ctx.pending_change.take();
+        if let Some(change) = ctx.change.take() {
+            self.last_change = Some(change);
+        }
+
+        //TODO: optimize redraw
+        ctx.e(Event::Redraw);
+    }
+
+    /// Provide completion candidates after [`Event::Complete`], replacing the command
+    /// line text from start to cursor. The first candidate is selected immediately, or the
+    /// last one if completion was requested with Shift-Tab.
+    pub fn set_completion<F: FnMut(Event)>(
+        &mut self,
+        start: usize,
+        candidates: Vec<String>,
+        mut callback: F,
+    ) {
+        let (value, cursor, search) = match &mut self.mode {
+            ViMode::Command { value, cursor } => (value, cursor, None),
+            ViMode::Search {
+                value,
+                cursor,
+                forwards,
+            } => (value, cursor, Some(*forwards)),
+            _ => return,
+        };
+        self.completion = Completion::new(value, start, *cursor, candidates);
+        if let Some(completion) = &mut self.completion {
+            if self.completion_forwards {
+                completion.next(value, cursor);
+            } else {
+                completion.previous(value, cursor);
+            }
+        }
+        if let Some(forwards) = search {
+            callback(search_preview(value, forwards));
+        }
+        callback(Event::Redraw);
+    }
 }
 
 impl Parser for ViParser {
     fn reset(&mut self) {
         self.mode = ViMode::Normal;
         self.cmd = ViCmd::default();
+        self.completion = None;
+        self.pending = None;
     }
 
+    // Key arms check the pending command inside the arm, as a guard would let the key fall
+    // through to a different arm
+    #[allow(clippy::collapsible_match, clippy::single_match)]
     fn parse<F: FnMut(Event)>(&mut self, key: Key, selection: bool, callback: F) {
-        // Makes composing commands easier
-        let cmd = &mut self.cmd;
         // Normalize key, so we don't deal with control characters below
-        let key = key.normalize();
+        let mut key = key.normalize();
         // Makes managing callbacks easier
         let mut ctx = ViContext {
             selection,
@@ -276,35 +919,121 @@ impl Parser for ViParser {
             set_mode: None,
         };
         let ctx = &mut ctx;
+        // Collect pending keys, like digraphs
+        if let Some(pending) = self.pending.take() {
+            match self.pending_key(pending, key, ctx) {
+                Some(pending_key) => key = pending_key,
+                None => {
+                    self.finish(ctx);
+                    return;
+                }
+            }
+        }
+        // Remember visual mode for gv when it is left
+        let visual_mode =
+            matches!(self.mode, ViMode::Visual | ViMode::VisualLine).then(|| self.mode.clone());
+        // Moving the cursor in replace mode starts a new replacement
+        if matches!(
+            key.code,
+            KeyCode::Down
+                | KeyCode::End
+                | KeyCode::Home
+                | KeyCode::Left
+                | KeyCode::PageDown
+                | KeyCode::PageUp
+                | KeyCode::Right
+                | KeyCode::Up
+                | KeyCode::Mouse(_)
+        ) {
+            self.replace_count = 0;
+        }
+        // Mouse keys are mostly the same in every mode
+        if let KeyCode::Mouse(mouse) = key.code {
+            self.mouse(mouse, ctx);
+            self.finish(ctx);
+            if visual_mode.is_some() && !matches!(self.mode, ViMode::Visual | ViMode::VisualLine) {
+                self.last_visual_mode = visual_mode;
+            }
+            return;
+        }
+        // Translate command characters typed with another keyboard layout, but not typed
+        // text or the character arguments of commands like f, t, and r
+        let command_key = match self.mode {
+            ViMode::Normal | ViMode::Visual | ViMode::VisualLine => true,
+            ViMode::Extra(extra) => !matches!(extra, 'f' | 'F' | 't' | 'T' | 'r' | 'R' | '"'),
+            _ => false,
+        };
+        if command_key && key.modifiers.is_empty() {
+            if let KeyCode::Char(c) = key.code {
+                key.code = KeyCode::Char(self.langmap.get(c));
+            }
+        }
+        // Makes composing commands easier
+        let cmd = &mut self.cmd;
         match self.mode {
-            ViMode::Normal | ViMode::Visual | ViMode::VisualLine => match key {
-                Key::Backspace => cmd.motion(Motion::Left, ctx),
+            ViMode::Normal | ViMode::Visual | ViMode::VisualLine => match (key.modifiers, key.code)
+            {
+                (Modifiers::NONE, KeyCode::Backspace) => cmd.motion(Motion::Left, ctx),
                 //TODO: what should backtab do?
-                Key::Backtab => (),
-                Key::Delete => {
+                (Modifiers::SHIFT, KeyCode::Tab) => (),
+                //TODO: what should ctrl backspace and ctrl delete do?
+                (Modifiers::CTRL, KeyCode::Backspace) | (Modifiers::CTRL, KeyCode::Delete) => (),
+                // Like vim, same as gg and G
+                (Modifiers::CTRL, KeyCode::End) => cmd.motion(Motion::GotoEof, ctx),
+                (Modifiers::CTRL, KeyCode::Home) => cmd.motion(Motion::GotoLine(1), ctx),
+                // Like vim, same as B and W
+                (Modifiers::CTRL, KeyCode::Left) => {
+                    cmd.motion(Motion::PreviousWordStart(Word::Upper), ctx);
+                }
+                (Modifiers::CTRL, KeyCode::Right) => {
+                    cmd.motion(Motion::NextWordStart(Word::Upper), ctx);
+                }
+                (Modifiers::NONE, KeyCode::Delete) => {
                     ctx.start_change();
                     cmd.repeat(|_| ctx.e(Event::DeleteInLine));
                     ctx.finish_change();
                 }
-                Key::Down => cmd.motion(Motion::Down, ctx),
-                Key::End => cmd.motion(Motion::End, ctx),
-                Key::Enter => {
+                (Modifiers::NONE, KeyCode::Down) => cmd.motion(Motion::Down, ctx),
+                (Modifiers::NONE, KeyCode::End) => cmd.motion(Motion::End, ctx),
+                (Modifiers::NONE, KeyCode::Enter) => {
                     cmd.motion(Motion::Down, ctx);
                     cmd.motion(Motion::SoftHome, ctx);
                 }
-                Key::Escape => {
+                (Modifiers::NONE, KeyCode::Escape) => {
                     self.reset();
                     ctx.e(Event::Escape);
                 }
-                Key::Home => cmd.motion(Motion::Home, ctx),
-                Key::Left => cmd.motion(Motion::LeftInLine, ctx),
-                Key::PageDown => cmd.motion(Motion::PageDown, ctx),
-                Key::PageUp => cmd.motion(Motion::PageUp, ctx),
-                Key::Right => cmd.motion(Motion::RightInLine, ctx),
+                (Modifiers::NONE, KeyCode::Home) => cmd.motion(Motion::Home, ctx),
+                // Same as i
+                (Modifiers::NONE, KeyCode::Insert) => {
+                    if cmd.operator.is_none() && self.mode == ViMode::Normal {
+                        ctx.start_change();
+                        self.mode = ViMode::Insert;
+                    }
+                }
+                (Modifiers::NONE, KeyCode::Left) => cmd.motion(Motion::LeftInLine, ctx),
+                (Modifiers::NONE, KeyCode::PageDown) => cmd.motion(Motion::PageDown, ctx),
+                (Modifiers::NONE, KeyCode::PageUp) => cmd.motion(Motion::PageUp, ctx),
+                (Modifiers::NONE, KeyCode::Right) => cmd.motion(Motion::RightInLine, ctx),
+                // Like vim, same as b and w, and scroll a page with up and down
+                (Modifiers::SHIFT, KeyCode::Down) | (Modifiers::SHIFT, KeyCode::PageDown) => {
+                    cmd.motion(Motion::PageDown, ctx);
+                }
+                (Modifiers::SHIFT, KeyCode::End) => cmd.motion(Motion::End, ctx),
+                (Modifiers::SHIFT, KeyCode::Home) => cmd.motion(Motion::Home, ctx),
+                (Modifiers::SHIFT, KeyCode::Left) => {
+                    cmd.motion(Motion::PreviousWordStart(Word::Lower), ctx);
+                }
+                (Modifiers::SHIFT, KeyCode::Right) => {
+                    cmd.motion(Motion::NextWordStart(Word::Lower), ctx);
+                }
+                (Modifiers::SHIFT, KeyCode::Up) | (Modifiers::SHIFT, KeyCode::PageUp) => {
+                    cmd.motion(Motion::PageUp, ctx);
+                }
                 //TODO: what should tab do?
-                Key::Tab => (),
-                Key::Up => cmd.motion(Motion::Up, ctx),
-                Key::Char(c) => match c {
+                (Modifiers::NONE, KeyCode::Tab) => (),
+                (Modifiers::NONE, KeyCode::Up) => cmd.motion(Motion::Up, ctx),
+                (Modifiers::NONE, KeyCode::Char(c)) => match c {
                     // Enter insert mode after cursor (if not awaiting text object)
                     'a' => {
                         if cmd.operator.is_some() || self.mode != ViMode::Normal {
@@ -453,6 +1182,7 @@ impl Parser for ViParser {
                     // Replace mode
                     'R' => {
                         ctx.start_change();
+                        self.replace_count = 0;
                         self.mode = ViMode::Replace;
                     }
                     // Substitute char (if not text object)
@@ -587,12 +1317,20 @@ impl Parser for ViParser {
                     }
                     // Auto indent
                     '=' => cmd.operator(Operator::AutoIndent, ctx),
-                    // TODO (if not text object)
-                    '[' => if !cmd.text_object(TextObject::SquareBrackets, ctx) {},
+                    // [ commands (if not text object)
+                    '[' => {
+                        if !cmd.text_object(TextObject::SquareBrackets, ctx) {
+                            self.mode = ViMode::Extra(c);
+                        }
+                    }
                     // TODO (if not text object)
                     '{' => if !cmd.text_object(TextObject::CurlyBrackets, ctx) {},
-                    // TODO (if not text object)
-                    ']' => if !cmd.text_object(TextObject::SquareBrackets, ctx) {},
+                    // ] commands (if not text object)
+                    ']' => {
+                        if !cmd.text_object(TextObject::SquareBrackets, ctx) {
+                            self.mode = ViMode::Extra(c);
+                        }
+                    }
                     // TODO (if not text object)
                     '}' => if !cmd.text_object(TextObject::CurlyBrackets, ctx) {},
                     // Repeat f/F/t/T
@@ -603,8 +1341,10 @@ impl Parser for ViParser {
                     }
                     // Enter command mode
                     ':' => {
+                        self.command_history.reset_recall();
                         self.mode = ViMode::Command {
                             value: String::new(),
+                            cursor: 0,
                         };
                     }
                     //TODO (if not text object)
@@ -648,15 +1388,19 @@ impl Parser for ViParser {
                     }
                     // Enter search mode
                     '/' => {
+                        self.search_history.reset_recall();
                         self.mode = ViMode::Search {
                             value: String::new(),
+                            cursor: 0,
                             forwards: true,
                         };
                     }
                     // Enter search backwards mode
                     '?' => {
+                        self.search_history.reset_recall();
                         self.mode = ViMode::Search {
                             value: String::new(),
+                            cursor: 0,
                             forwards: false,
                         };
                     }
@@ -664,15 +1408,28 @@ impl Parser for ViParser {
                     ' ' => cmd.motion(Motion::Right, ctx),
                     _ => {}
                 },
-                Key::Ctrl(c) => {
+                // Alternate buffer, or buffer by number with a count
+                (Modifiers::CTRL, KeyCode::Char('^' | '6')) => {
+                    ctx.e(Event::Buffer(match cmd.count.take() {
+                        Some(number) => BufferCommand::Number(number),
+                        None => BufferCommand::Alternate,
+                    }));
+                }
+                // Window commands
+                (Modifiers::CTRL, KeyCode::Char('w')) => {
+                    self.mode = ViMode::Extra(VI_WINDOW_EXTRA);
+                }
+                (Modifiers::CTRL, KeyCode::Char(_c)) => {
                     //TODO: Ctrl characters
                 }
+                //TODO: what should alt keys and other keys do?
+                _ => (),
             },
             ViMode::Extra(extra) => match extra {
                 // Find/till character
                 'f' | 'F' | 't' | 'T' => {
-                    match key {
-                        Key::Char(c) => {
+                    match (key.modifiers, key.code) {
+                        (Modifiers::NONE, KeyCode::Char(c)) => {
                             let motion = match extra {
                                 'f' => Motion::NextChar(c),
                                 'F' => Motion::PreviousChar(c),
@@ -689,8 +1446,23 @@ impl Parser for ViParser {
                 }
                 // Extra commands
                 'g' => {
-                    match key {
-                        Key::Char(c) => match c {
+                    match (key.modifiers, key.code) {
+                        (Modifiers::NONE, KeyCode::Char(c)) => match c {
+                            // Repeat last substitute on all lines
+                            '&' => {
+                                ctx.start_change();
+                                ctx.e(Event::SubstituteRepeatAll);
+                                ctx.finish_change();
+                            }
+                            // Show UTF-8 bytes of character
+                            '8' => ctx.e(Event::CharInfoUtf8),
+                            // Last non-blank character of line
+                            '_' => {
+                                let lines = cmd.count.take().unwrap_or(1).saturating_sub(1);
+                                cmd.motion(Motion::SoftEnd(lines), ctx);
+                            }
+                            // Show character code
+                            'a' => ctx.e(Event::CharInfo),
                             // Previous word end
                             'e' => cmd.motion(Motion::PreviousWordEnd(Word::Lower), ctx),
                             // Prevous WORD end
@@ -699,6 +1471,34 @@ impl Parser for ViParser {
                                 Some(line) => cmd.motion(Motion::GotoLine(line), ctx),
                                 None => cmd.motion(Motion::GotoLine(1), ctx),
                             },
+                            // Insert where insert mode was last stopped
+                            'i' => {
+                                if cmd.operator.is_none() {
+                                    ctx.start_change();
+                                    ViCmd::default().motion(Motion::LastInsert, ctx);
+                                    self.mode = ViMode::Insert;
+                                }
+                            }
+                            // Insert at column 1
+                            'I' => {
+                                if cmd.operator.is_none() {
+                                    ctx.start_change();
+                                    ViCmd::default().motion(Motion::Home, ctx);
+                                    self.mode = ViMode::Insert;
+                                }
+                            }
+                            // Join lines without inserting or removing spaces
+                            'J' => {
+                                // Like vim, a count of lines to join includes the line at cursor
+                                let joins = cmd.count.take().unwrap_or(2).saturating_sub(1).max(1);
+                                ctx.start_change();
+                                for _ in 0..joins {
+                                    ctx.e(Event::Join { spaces: false });
+                                }
+                                ctx.finish_change();
+                            }
+                            // Middle of screen line
+                            'm' => cmd.motion(Motion::ScreenLineMiddle, ctx),
                             'n' => {
                                 cmd.motion(Motion::Inside, ctx);
                                 cmd.text_object(TextObject::Search { forwards: true }, ctx);
@@ -707,33 +1507,299 @@ impl Parser for ViParser {
                                 cmd.motion(Motion::Inside, ctx);
                                 cmd.text_object(TextObject::Search { forwards: false }, ctx);
                             }
+                            // Goto byte
+                            'o' => {
+                                let byte = cmd.count.take().unwrap_or(1);
+                                cmd.motion(Motion::GotoByte(byte), ctx);
+                            }
+                            // Paste after or before, moving cursor after the new text
+                            'p' | 'P' => {
+                                if cmd.operator.is_none() {
+                                    let register = cmd.register.unwrap_or(VI_DEFAULT_REGISTER);
+                                    ctx.start_change();
+                                    cmd.repeat(|_| {
+                                        ctx.e(Event::PutAndMove {
+                                            register,
+                                            after: c == 'p',
+                                        });
+                                    });
+                                    ctx.finish_change();
+                                }
+                            }
+                            // Next tab page, or tab page by number with a count
+                            't' => ctx.e(Event::TabPage(match cmd.count.take() {
+                                Some(number) => TabPageCommand::Number(number),
+                                None => TabPageCommand::Next,
+                            })),
+                            // Previous tab page
+                            'T' => ctx.e(Event::TabPage(TabPageCommand::Previous(
+                                cmd.count.take().unwrap_or(1),
+                            ))),
+                            // Virtual replace char, R never waits for a character so it is used
+                            'r' => {
+                                self.mode = ViMode::Extra('R');
+                            }
+                            // Virtual replace mode
+                            'R' => {
+                                ctx.start_change();
+                                self.replace_count = 0;
+                                self.mode = ViMode::VirtualReplace;
+                            }
+                            // Reselect last visual selection
+                            'v' => {
+                                if let Some(mode) = self.last_visual_mode.clone() {
+                                    if cmd.operator.is_none() {
+                                        ctx.e(Event::SelectLast);
+                                        self.mode = mode;
+                                    }
+                                }
+                            }
                             //TODO: more g commands
                             _ => {}
                         },
                         //TODO: what do control keys do in this mode?
                         _ => {}
                     }
+                    // Keep mode if it was changed, like by gi or gR
+                    let mode = mem::replace(&mut self.mode, ViMode::Normal);
                     self.reset();
+                    if mode != ViMode::Extra('g') {
+                        self.mode = mode;
+                    }
                 }
-                // Replace character
-                'r' => {
-                    match key {
-                        Key::Char(c) => {
+                // Replace character, or virtual replace character after g
+                'r' | 'R' => {
+                    match (key.modifiers, key.code) {
+                        (Modifiers::NONE, KeyCode::Char(c)) => {
                             //TODO: a visual selection allows replacing all characters
                             ctx.start_change();
-                            ctx.e(Event::Delete);
-                            ctx.e(Event::Insert(c));
-                            ViCmd::default().motion(Motion::LeftInLine, ctx);
+                            if extra == 'R' {
+                                ctx.e(Event::VirtualReplaceChar(c));
+                                ViCmd::default().motion(Motion::LeftInLine, ctx);
+                            } else {
+                                // Like vim, there is nothing to replace at end of line
+                                ctx.e(Event::ReplaceCharInLine(c));
+                            }
                             ctx.finish_change();
                         }
                         _ => {}
                     }
                     self.reset();
                 }
+                // View commands
+                'z' => {
+                    let scroll = match (key.modifiers, key.code) {
+                        (Modifiers::NONE, KeyCode::Char('t')) => Some((ScrollPosition::Top, false)),
+                        (Modifiers::NONE, KeyCode::Enter) => Some((ScrollPosition::Top, true)),
+                        (Modifiers::NONE, KeyCode::Char('z')) => {
+                            Some((ScrollPosition::Center, false))
+                        }
+                        (Modifiers::NONE, KeyCode::Char('.')) => {
+                            Some((ScrollPosition::Center, true))
+                        }
+                        (Modifiers::NONE, KeyCode::Char('b')) => {
+                            Some((ScrollPosition::Bottom, false))
+                        }
+                        (Modifiers::NONE, KeyCode::Char('-')) => {
+                            Some((ScrollPosition::Bottom, true))
+                        }
+                        _ => None,
+                    };
+                    match scroll {
+                        Some((position, reset_column)) => {
+                            // A count is the line to scroll to
+                            if let Some(line) = cmd.count.take() {
+                                ViCmd::default().motion(Motion::GotoLine(line), ctx);
+                            }
+                            ctx.e(Event::ScrollCursor {
+                                position,
+                                reset_column,
+                            });
+                        }
+                        None => match (key.modifiers, key.code) {
+                            // Create fold
+                            (Modifiers::NONE, KeyCode::Char('f')) => {
+                                self.mode = ViMode::Normal;
+                                cmd.operator(Operator::Fold, ctx);
+                            }
+                            // Create fold for count lines
+                            (Modifiers::NONE, KeyCode::Char('F')) => {
+                                self.mode = ViMode::Normal;
+                                cmd.operator(Operator::Fold, ctx);
+                                // The lines after the first are selected by moving down
+                                match cmd.count.take().filter(|count| *count > 1) {
+                                    Some(count) => {
+                                        cmd.count = Some(count.saturating_sub(1));
+                                        cmd.linewise = true;
+                                        cmd.motion(Motion::Down, ctx);
+                                    }
+                                    None => cmd.motion(Motion::Line, ctx),
+                                }
+                            }
+                            (Modifiers::NONE, KeyCode::Char('o')) => {
+                                cmd.repeat(|_| ctx.e(Event::FoldOpen));
+                            }
+                            (Modifiers::NONE, KeyCode::Char('c')) => {
+                                cmd.repeat(|_| ctx.e(Event::FoldClose));
+                            }
+                            (Modifiers::NONE, KeyCode::Char('a')) => ctx.e(Event::FoldToggle),
+                            (Modifiers::NONE, KeyCode::Char('R')) => ctx.e(Event::FoldOpenAll),
+                            (Modifiers::NONE, KeyCode::Char('M')) => ctx.e(Event::FoldCloseAll),
+                            (Modifiers::NONE, KeyCode::Char('d')) => ctx.e(Event::FoldDelete),
+                            (Modifiers::NONE, KeyCode::Char('E')) => ctx.e(Event::FoldDeleteAll),
+                            (Modifiers::NONE, KeyCode::Char('j')) => {
+                                cmd.motion(Motion::NextFoldStart, ctx);
+                            }
+                            (Modifiers::NONE, KeyCode::Char('k')) => {
+                                cmd.motion(Motion::PreviousFoldEnd, ctx);
+                            }
+                            (Modifiers::NONE, KeyCode::Char('h'))
+                            | (Modifiers::NONE, KeyCode::Left) => {
+                                ctx.e(Event::ScrollLeft(cmd.count.take().unwrap_or(1)));
+                            }
+                            (Modifiers::NONE, KeyCode::Char('l'))
+                            | (Modifiers::NONE, KeyCode::Right) => {
+                                ctx.e(Event::ScrollRight(cmd.count.take().unwrap_or(1)));
+                            }
+                            _ => {
+                                //TODO: more z commands
+                                log::info!("TODO: extra command {:?}{:?}", extra, key);
+                            }
+                        },
+                    }
+                    // Keep operator for zf
+                    if self.mode == ViMode::Extra('z') {
+                        self.reset();
+                    }
+                }
+                // Window commands
+                VI_WINDOW_EXTRA => {
+                    // Control keys work the same as characters, like Ctrl-W Ctrl-J
+                    let c = match (key.modifiers, key.code) {
+                        (Modifiers::NONE, KeyCode::Char(c))
+                        | (Modifiers::CTRL, KeyCode::Char(c)) => Some(c),
+                        (Modifiers::NONE, KeyCode::Left) => Some('h'),
+                        (Modifiers::NONE, KeyCode::Down) => Some('j'),
+                        (Modifiers::NONE, KeyCode::Up) => Some('k'),
+                        (Modifiers::NONE, KeyCode::Right) => Some('l'),
+                        _ => None,
+                    };
+                    let count = cmd.count;
+                    let moves = count.unwrap_or(1);
+                    let lines = isize::try_from(moves).unwrap_or(isize::MAX);
+                    let command = match c {
+                        // Count can be typed after Ctrl-W
+                        Some(c @ '0'..='9') if c != '0' || count.is_some() => {
+                            let number = c.to_digit(10).unwrap_or(0) as usize;
+                            cmd.count =
+                                Some(count.unwrap_or(0).saturating_mul(10).saturating_add(number));
+                            None
+                        }
+                        Some('s' | 'S') => Some(WindowCommand::Split {
+                            size: count,
+                            path: None,
+                        }),
+                        Some('v') => Some(WindowCommand::VerticalSplit {
+                            size: count,
+                            path: None,
+                        }),
+                        Some('c') => Some(WindowCommand::Close { force: false }),
+                        Some('o') => Some(WindowCommand::Only { force: false }),
+                        Some('h') => Some(WindowCommand::Focus(WindowFocus::Left(moves))),
+                        Some('j') => Some(WindowCommand::Focus(WindowFocus::Down(moves))),
+                        Some('k') => Some(WindowCommand::Focus(WindowFocus::Up(moves))),
+                        Some('l') => Some(WindowCommand::Focus(WindowFocus::Right(moves))),
+                        Some('w') => Some(WindowCommand::Focus(match count {
+                            Some(number) => WindowFocus::Number(number),
+                            None => WindowFocus::Next,
+                        })),
+                        Some('W') => Some(WindowCommand::Focus(match count {
+                            Some(number) => WindowFocus::Number(number),
+                            None => WindowFocus::Previous,
+                        })),
+                        Some('p') => Some(WindowCommand::Focus(WindowFocus::Last)),
+                        Some('t') => Some(WindowCommand::Focus(WindowFocus::Top)),
+                        Some('b') => Some(WindowCommand::Focus(WindowFocus::Bottom)),
+                        Some('+') => Some(WindowCommand::Resize(WindowResize::Height(lines))),
+                        Some('-') => Some(WindowCommand::Resize(WindowResize::Height(
+                            lines.saturating_neg(),
+                        ))),
+                        Some('>') => Some(WindowCommand::Resize(WindowResize::Width(lines))),
+                        Some('<') => Some(WindowCommand::Resize(WindowResize::Width(
+                            lines.saturating_neg(),
+                        ))),
+                        Some('=') => Some(WindowCommand::Resize(WindowResize::Equal)),
+                        Some('_') => Some(WindowCommand::Resize(WindowResize::SetHeight(count))),
+                        Some('|') => Some(WindowCommand::Resize(WindowResize::SetWidth(count))),
+                        Some('r') => Some(WindowCommand::Rotate {
+                            downwards: true,
+                            count: moves,
+                        }),
+                        Some('R') => Some(WindowCommand::Rotate {
+                            downwards: false,
+                            count: moves,
+                        }),
+                        Some('x') => Some(WindowCommand::Exchange(count)),
+                        // Quit window
+                        Some('q') => {
+                            ctx.e(Event::Quit { force: false });
+                            self.reset();
+                            None
+                        }
+                        _ => {
+                            //TODO: more window commands
+                            log::info!("TODO: window command {:?}", key);
+                            self.reset();
+                            None
+                        }
+                    };
+                    if let Some(command) = command {
+                        ctx.e(Event::Window(command));
+                        self.reset();
+                    }
+                }
+                // Write and quit
+                'Z' => {
+                    match (key.modifiers, key.code) {
+                        (Modifiers::NONE, KeyCode::Char('Z')) => {
+                            ctx.e(Event::WriteQuit(WriteRequest {
+                                range: None,
+                                path: None,
+                                force: false,
+                                modified_only: true,
+                                save_as: false,
+                            }));
+                        }
+                        (Modifiers::NONE, KeyCode::Char('Q')) => ctx.e(Event::Quit { force: true }),
+                        _ => {
+                            log::info!("TODO: extra command {:?}{:?}", extra, key);
+                        }
+                    }
+                    self.reset();
+                }
+                // Bracket commands
+                '[' | ']' => {
+                    match (key.modifiers, key.code) {
+                        // Start or end of fold
+                        (Modifiers::NONE, KeyCode::Char('z')) => cmd.motion(
+                            if extra == '[' {
+                                Motion::FoldStart
+                            } else {
+                                Motion::FoldEnd
+                            },
+                            ctx,
+                        ),
+                        _ => {
+                            //TODO: more bracket commands
+                            log::info!("TODO: extra command {:?}{:?}", extra, key);
+                        }
+                    }
+                    self.reset();
+                }
                 // Select register
                 '"' => {
-                    match key {
-                        Key::Char(c) => {
+                    match (key.modifiers, key.code) {
+                        (Modifiers::NONE, KeyCode::Char(c)) => {
                             cmd.register = Some(c);
                         }
                         _ => {}
@@ -747,97 +1813,615 @@ impl Parser for ViParser {
                     self.reset();
                 }
             },
-            ViMode::Insert | ViMode::Replace => match key {
-                //TODO: FINISH CHANGE ON MOTION?
-                Key::Backspace => ctx.e(Event::Backspace),
-                Key::Backtab => ctx.e(Event::ShiftLeft),
-                Key::Char(c) => {
-                    if self.mode == ViMode::Replace {
-                        ctx.e(Event::Delete);
+            ViMode::Insert | ViMode::Replace | ViMode::VirtualReplace => {
+                match (key.modifiers, key.code) {
+                    //TODO: FINISH CHANGE ON MOTION?
+                    (Modifiers::NONE, KeyCode::Backspace) => {
+                        if self.mode == ViMode::Insert {
+                            ctx.e(Event::Backspace);
+                        } else if let Some(count) = self.replace_count.checked_sub(1) {
+                            // Restore what was replaced
+                            self.replace_count = count;
+                            ctx.e(Event::ReplaceBackspace);
+                        } else {
+                            // Like vim, only move before the start of the replacement
+                            ViCmd::default().motion(Motion::Left, ctx);
+                        }
                     }
-                    ctx.e(Event::Insert(c));
-                }
-                Key::Ctrl(c) => {
-                    //TODO: control characters
+                    (Modifiers::SHIFT, KeyCode::Tab) => ctx.e(Event::ShiftLeft),
+                    (Modifiers::NONE, KeyCode::Char(c)) => self.insert_char(c, ctx),
+                    // Digraph
+                    (Modifiers::CTRL, KeyCode::Char('k')) => {
+                        self.pending = Some(ViPending::Digraph(None));
+                    }
+                    // Literal key or character code
+                    (Modifiers::CTRL, KeyCode::Char('v'))
+                    | (Modifiers::CTRL, KeyCode::Char('q')) => {
+                        self.pending = Some(ViPending::Literal {
+                            prefix: None,
+                            value: None,
+                            len: 0,
+                        });
+                    }
+                    (Modifiers::CTRL, KeyCode::Char(_c)) => {
+                        //TODO: control characters
+                    }
+                    //TODO: delete words with ctrl backspace and ctrl delete
+                    (Modifiers::CTRL, KeyCode::Backspace) | (Modifiers::CTRL, KeyCode::Delete) => {}
+                    // Like vim, same as the motions in normal mode
+                    (Modifiers::CTRL, KeyCode::End) => {
+                        ViCmd::default().motion(Motion::GotoEof, ctx);
+                    }
+                    (Modifiers::CTRL, KeyCode::Home) => {
+                        ViCmd::default().motion(Motion::GotoLine(1), ctx);
+                    }
+                    (Modifiers::CTRL, KeyCode::Left) | (Modifiers::SHIFT, KeyCode::Left) => {
+                        ViCmd::default().motion(Motion::PreviousWordStart(Word::Lower), ctx);
+                    }
+                    (Modifiers::CTRL, KeyCode::Right) | (Modifiers::SHIFT, KeyCode::Right) => {
+                        ViCmd::default().motion(Motion::NextWordStart(Word::Lower), ctx);
+                    }
+                    (Modifiers::SHIFT, KeyCode::Down) | (Modifiers::SHIFT, KeyCode::PageDown) => {
+                        ViCmd::default().motion(Motion::PageDown, ctx);
+                    }
+                    (Modifiers::SHIFT, KeyCode::End) => ViCmd::default().motion(Motion::End, ctx),
+                    (Modifiers::SHIFT, KeyCode::Home) => ViCmd::default().motion(Motion::Home, ctx),
+                    (Modifiers::SHIFT, KeyCode::Up) | (Modifiers::SHIFT, KeyCode::PageUp) => {
+                        ViCmd::default().motion(Motion::PageUp, ctx);
+                    }
+                    (Modifiers::NONE, KeyCode::Down) => ViCmd::default().motion(Motion::Down, ctx),
+                    (Modifiers::NONE, KeyCode::Delete) => ctx.e(Event::Delete),
+                    (Modifiers::NONE, KeyCode::End) => ViCmd::default().motion(Motion::End, ctx),
+                    (Modifiers::NONE, KeyCode::Enter) => {
+                        if self.mode != ViMode::Insert {
+                            //TODO: virtual replace should replace the rest of the line instead
+                            // Line breaks are inserted, but can be removed with backspace
+                            self.replace_count = self.replace_count.saturating_add(1);
+                        }
+                        ctx.e(Event::NewLine);
+                    }
+                    (Modifiers::NONE, KeyCode::Escape) => {
+                        ViCmd::default().motion(Motion::LeftInLine, ctx);
+                        ctx.finish_change();
+                        self.reset();
+                    }
+                    (Modifiers::NONE, KeyCode::Home) => ViCmd::default().motion(Motion::Home, ctx),
+                    // Toggle between insert and replace
+                    (Modifiers::NONE, KeyCode::Insert) => {
+                        if self.mode == ViMode::Insert {
+                            self.replace_count = 0;
+                            self.mode = ViMode::Replace;
+                        } else {
+                            self.mode = ViMode::Insert;
+                        }
+                    }
+                    (Modifiers::NONE, KeyCode::Left) => {
+                        ViCmd::default().motion(Motion::LeftInLine, ctx);
+                    }
+                    (Modifiers::NONE, KeyCode::PageDown) => {
+                        ViCmd::default().motion(Motion::PageDown, ctx);
+                    }
+                    (Modifiers::NONE, KeyCode::PageUp) => {
+                        ViCmd::default().motion(Motion::PageUp, ctx);
+                    }
+                    (Modifiers::NONE, KeyCode::Right) => {
+                        ViCmd::default().motion(Motion::RightInLine, ctx);
+                    }
+                    (Modifiers::NONE, KeyCode::Tab) => ctx.e(Event::ShiftRight),
+                    (Modifiers::NONE, KeyCode::Up) => ViCmd::default().motion(Motion::Up, ctx),
+                    //TODO: what should alt keys and other keys do?
+                    _ => {}
                 }
-                Key::Down => ViCmd::default().motion(Motion::Down, ctx),
-                Key::Delete => ctx.e(Event::Delete),
-                Key::End => ViCmd::default().motion(Motion::End, ctx),
-                Key::Enter => ctx.e(Event::NewLine),
-                Key::Escape => {
-                    ViCmd::default().motion(Motion::LeftInLine, ctx);
-                    ctx.finish_change();
+            }
+            ViMode::Command {
+                ref mut value,
+                ref mut cursor,
+            } => match (key.modifiers, key.code) {
+                (Modifiers::NONE, KeyCode::Escape) => {
                     self.reset();
                 }
-                Key::Home => ViCmd::default().motion(Motion::Home, ctx),
-                Key::Left => ViCmd::default().motion(Motion::LeftInLine, ctx),
-                Key::PageDown => ViCmd::default().motion(Motion::PageDown, ctx),
-                Key::PageUp => ViCmd::default().motion(Motion::PageUp, ctx),
-                Key::Right => ViCmd::default().motion(Motion::RightInLine, ctx),
-                Key::Tab => ctx.e(Event::ShiftRight),
-                Key::Up => ViCmd::default().motion(Motion::Up, ctx),
-            },
-            ViMode::Command { ref mut value } => match key {
-                Key::Escape => {
+                (Modifiers::NONE, KeyCode::Enter) => {
+                    self.command_history.push(value.clone());
+                    match ExCommand::parse(value) {
+                        Some(command) => {
+                            // Other commands are run by the editor, an empty command does
+                            // nothing
+                            if !ex_run(&command, &mut self.digraphs, ctx)
+                                && (command.range.is_some() || !command.name.is_empty())
+                            {
+                                ctx.e(Event::Ex {
+                                    range: command.range,
+                                    name: command.name.into(),
+                                    bang: command.bang,
+                                    args: command.args.into(),
+                                });
+                            }
+                        }
+                        None => log::warn!("invalid range in {:?}", value),
+                    }
                     self.reset();
                 }
-                Key::Enter => {
-                    //TODO: run command
-                    self.reset();
+                (Modifiers::NONE, KeyCode::Tab) | (Modifiers::CTRL, KeyCode::Char('n'))
+                    if self.completion.is_some() =>
+                {
+                    if let Some(completion) = &mut self.completion {
+                        completion.next(value, cursor);
+                    }
                 }
-                Key::Backspace => {
-                    if value.pop().is_none() {
-                        self.reset();
+                (Modifiers::SHIFT, KeyCode::Tab) | (Modifiers::CTRL, KeyCode::Char('p'))
+                    if self.completion.is_some() =>
+                {
+                    if let Some(completion) = &mut self.completion {
+                        completion.previous(value, cursor);
                     }
                 }
-                Key::Char(c) => {
-                    value.push(c);
+                // Digraph
+                (Modifiers::CTRL, KeyCode::Char('k')) => {
+                    self.pending = Some(ViPending::Digraph(None));
+                }
+                (Modifiers::NONE, KeyCode::Tab) | (Modifiers::SHIFT, KeyCode::Tab) => {
+                    self.completion_forwards = key.modifiers == Modifiers::NONE;
+                    ctx.e(Event::Complete {
+                        value: value.clone(),
+                        cursor: *cursor,
+                        search: false,
+                    });
                 }
                 _ => {
-                    //TODO: more keys
+                    // Any other key accepts the completion
+                    self.completion = None;
+                    if !cmdline_key(value, cursor, &mut self.command_history, key) {
+                        self.reset();
+                    }
                 }
             },
             ViMode::Search {
                 ref mut value,
+                ref mut cursor,
                 forwards,
-            } => match key {
-                Key::Escape => {
+            } => match (key.modifiers, key.code) {
+                (Modifiers::NONE, KeyCode::Escape) => {
+                    ctx.e(Event::SearchPreviewCancel);
                     self.reset();
                 }
-                Key::Enter => {
+                (Modifiers::NONE, KeyCode::Enter) => {
+                    let request = SearchRequest::parse(value, forwards);
+                    if request.is_none() {
+                        log::warn!("invalid search offset in {:?}", value);
+                    }
                     // Swap search value to avoid allocations
                     let mut tmp = String::new();
                     mem::swap(value, &mut tmp);
-                    ctx.e(Event::SetSearch(tmp, forwards));
-                    self.reset();
-                    ViCmd::default().motion(Motion::NextSearch, ctx);
+                    self.search_history.push(tmp);
+                    ctx.e(Event::SearchPreviewCancel);
+                    match request {
+                        Some(request) => {
+                            // Keep pending operator and count, so d/foo/e works
+                            self.mode = ViMode::Normal;
+                            self.completion = None;
+                            cmd.linewise = request.linewise();
+                            cmd.inclusive = request.inclusive();
+                            ctx.e(Event::SetSearch(request));
+                            cmd.motion(Motion::NextSearch, ctx);
+                        }
+                        // Like vim, an invalid search cancels the pending operator
+                        None => self.reset(),
+                    }
                 }
-                Key::Backspace => {
-                    if value.pop().is_none() {
-                        self.reset();
+                (Modifiers::NONE, KeyCode::Tab) | (Modifiers::CTRL, KeyCode::Char('n'))
+                    if self.completion.is_some() =>
+                {
+                    if let Some(completion) = &mut self.completion {
+                        completion.next(value, cursor);
+                    }
+                    ctx.e(search_preview(value, forwards));
+                }
+                (Modifiers::SHIFT, KeyCode::Tab) | (Modifiers::CTRL, KeyCode::Char('p'))
+                    if self.completion.is_some() =>
+                {
+                    if let Some(completion) = &mut self.completion {
+                        completion.previous(value, cursor);
                     }
+                    ctx.e(search_preview(value, forwards));
+                }
+                // Digraph
+                (Modifiers::CTRL, KeyCode::Char('k')) => {
+                    self.pending = Some(ViPending::Digraph(None));
                 }
-                Key::Char(c) => {
-                    value.push(c);
+                (Modifiers::NONE, KeyCode::Tab) | (Modifiers::SHIFT, KeyCode::Tab) => {
+                    self.completion_forwards = key.modifiers == Modifiers::NONE;
+                    ctx.e(Event::Complete {
+                        value: value.clone(),
+                        cursor: *cursor,
+                        search: true,
+                    });
                 }
                 _ => {
-                    //TODO: more keys
+                    // Any other key accepts the completion
+                    self.completion = None;
+                    let previous = value.clone();
+                    if !cmdline_key(value, cursor, &mut self.search_history, key) {
+                        ctx.e(Event::SearchPreviewCancel);
+                        self.reset();
+                    } else if *value != previous {
+                        // Preview search on every change, like vim's incsearch
+                        ctx.e(search_preview(value, forwards));
+                    }
                 }
             },
         }
 
-        // Reset mode after operators
-        if let Some(mode) = ctx.set_mode.take() {
-            self.mode = mode;
+        self.finish(ctx);
+        if visual_mode.is_some() && !matches!(self.mode, ViMode::Visual | ViMode::VisualLine) {
+            self.last_visual_mode = visual_mode;
         }
+    }
 
-        // Save change state
-        self.pending_change = ctx.pending_change.take();
-        if let Some(change) = ctx.change.take() {
-            self.last_change = Some(change);
+    fn compose<F: FnMut(Event)>(
+        &mut self,
+        composition: Composition,
+        selection: bool,
+        mut callback: F,
+    ) {
+        // Composed text is not part of a change, so it is not repeated with .
+        let text = match composition {
+            Composition::Start => return,
+            Composition::Update { text, cursor } => {
+                callback(Event::Preedit { text, cursor });
+                callback(Event::Redraw);
+                return;
+            }
+            Composition::Commit(text) => text,
+        };
+        callback(Event::Preedit {
+            text: String::new(),
+            cursor: None,
+        });
+        match self.mode {
+            // Committed text is inserted at once, like pasted text
+            ViMode::Insert | ViMode::Command { .. } | ViMode::Search { .. } => {
+                if text.is_empty() {
+                    callback(Event::Redraw);
+                } else {
+                    self.paste(&text, selection, callback);
+                }
+            }
+            _ => {
+                for c in text.chars() {
+                    self.parse(Key::from(KeyCode::Char(c)), selection, &mut callback);
+                }
+            }
         }
+    }
 
-        //TODO: optimize redraw
-        ctx.e(Event::Redraw);
+    fn paste<F: FnMut(Event)>(&mut self, text: &str, selection: bool, callback: F) {
+        let mut ctx = ViContext {
+            selection,
+            callback,
+            pending_change: self.pending_change.take(),
+            change: None,
+            set_mode: None,
+        };
+        let ctx = &mut ctx;
+        // Pasting cancels digraphs and literal keys
+        self.pending = None;
+        let visual_mode =
+            matches!(self.mode, ViMode::Visual | ViMode::VisualLine).then(|| self.mode.clone());
+        match self.mode {
+            // Like vim, put the text before the cursor, replacing the selection, and cancel
+            // any pending command
+            ViMode::Normal | ViMode::Visual | ViMode::VisualLine | ViMode::Extra(_) => {
+                ctx.start_change();
+                if selection {
+                    ctx.e(Event::Delete);
+                    ctx.e(Event::SelectClear);
+                }
+                ctx.e(Event::PutText(text.into()));
+                ctx.finish_change();
+                self.reset();
+            }
+            ViMode::Insert => ctx.e(Event::InsertText(text.into())),
+            // Replace characters, so the text can be restored with backspace
+            ViMode::Replace | ViMode::VirtualReplace => {
+                for c in text.chars() {
+                    if c == '\n' {
+                        self.replace_count = self.replace_count.saturating_add(1);
+                        ctx.e(Event::NewLine);
+                    } else {
+                        self.insert_char(c, ctx);
+                    }
+                }
+            }
+            ViMode::Command {
+                ref mut value,
+                ref mut cursor,
+            } => {
+                self.completion = None;
+                cmdline_paste(value, cursor, &mut self.command_history, text);
+            }
+            ViMode::Search {
+                ref mut value,
+                ref mut cursor,
+                forwards,
+            } => {
+                self.completion = None;
+                cmdline_paste(value, cursor, &mut self.search_history, text);
+                ctx.e(search_preview(value, forwards));
+            }
+        }
+
+        self.finish(ctx);
+        if visual_mode.is_some() && !matches!(self.mode, ViMode::Visual | ViMode::VisualLine) {
+            self.last_visual_mode = visual_mode;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::{ExAddress, ExLine, ExRange};
+
+    fn parse_keys(parser: &mut ViParser, keys: &str) -> Vec<Event> {
+        let mut events = Vec::new();
+        parser.parse_keys(keys, false, |event| events.push(event));
+        events
+    }
+
+    #[test]
+    fn text_object() {
+        let events = parse_keys(&mut ViParser::new(), "diw");
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::SelectTextObject(TextObject::Word(Word::Lower), false)
+        )));
+        assert!(events.iter().any(|event| matches!(event, Event::Delete)));
+
+        // Without a text object nothing runs
+        let events = parse_keys(&mut ViParser::new(), "di<Esc>");
+        assert!(!events.iter().any(|event| matches!(event, Event::Delete)));
+    }
+
+    #[test]
+    fn fold_count() {
+        let events = parse_keys(&mut ViParser::new(), "3zF");
+        let downs = events
+            .iter()
+            .filter(|event| matches!(event, Event::Motion(Motion::Down)))
+            .count();
+        assert_eq!(downs, 2);
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::SelectLineStart)));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::FoldCreate)));
+    }
+
+    #[test]
+    fn replace_char() {
+        let events = parse_keys(&mut ViParser::new(), "rx");
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::ReplaceCharInLine('x'))));
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::ReplaceChar(_) | Event::Motion(_))));
+    }
+
+    #[test]
+    fn search_offset_inclusive() {
+        let right = |keys| {
+            parse_keys(&mut ViParser::new(), keys)
+                .iter()
+                .any(|event| matches!(event, Event::Motion(Motion::RightInLine)))
+        };
+        assert!(right("d/foo/e<CR>"));
+        assert!(!right("d/foo<CR>"));
+        assert!(!right("/foo/e<CR>"));
+    }
+
+    #[test]
+    fn search_offset_invalid() {
+        let mut parser = ViParser::new();
+        let events = parse_keys(&mut parser, "d/foo/x<CR>");
+        assert!(!events.iter().any(|event| matches!(
+            event,
+            Event::SetSearch(_) | Event::Motion(_) | Event::Delete
+        )));
+        assert!(matches!(events.last(), Some(Event::Redraw)));
+        assert_eq!(parser.mode, ViMode::Normal);
+        assert!(parser.cmd.operator.is_none());
+
+        let events = parse_keys(&mut ViParser::new(), "/foo/x");
+        assert!(matches!(
+            events.iter().rev().nth(1),
+            Some(Event::SearchPreviewCancel)
+        ));
+    }
+
+    #[test]
+    fn literal_decimal() {
+        let inserted = |keys| {
+            parse_keys(&mut ViParser::new(), keys)
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Insert(c) => Some(*c),
+                    _ => None,
+                })
+                .collect::<String>()
+        };
+        assert_eq!(inserted("i<C-v>065"), "A");
+        assert_eq!(inserted("i<C-v>2551"), "\u{FF}1");
+        assert_eq!(inserted("i<C-v>999"), "\u{FF}");
+        assert_eq!(inserted("i<C-v>o777"), "\u{FF}");
+        assert_eq!(inserted("i<C-v>u03e7"), "\u{3E7}");
+    }
+
+    #[test]
+    fn join() {
+        let joins = |keys| {
+            parse_keys(&mut ViParser::new(), keys)
+                .iter()
+                .filter(|event| matches!(event, Event::Join { spaces: false }))
+                .count()
+        };
+        assert_eq!(joins("gJ"), 1);
+        assert_eq!(joins("3gJ"), 2);
+    }
+
+    #[test]
+    fn cmdline_recall_and_delete() {
+        let command = |parser: &ViParser| match &parser.mode {
+            ViMode::Command { value, cursor } => Some((value.clone(), *cursor)),
+            _ => None,
+        };
+        let mut parser = ViParser::new();
+        parse_keys(&mut parser, ":set nu<CR>:w<CR>:set list<CR>:se<Up>");
+        assert_eq!(command(&parser), Some(("set list".into(), 8)));
+        parse_keys(&mut parser, "<Up>");
+        assert_eq!(command(&parser), Some(("set nu".into(), 6)));
+        parse_keys(&mut parser, "<Down>");
+        assert_eq!(command(&parser), Some(("set list".into(), 8)));
+        parse_keys(&mut parser, "<Down>");
+        assert_eq!(command(&parser), Some(("se".into(), 2)));
+
+        let mut parser = ViParser::new();
+        parse_keys(&mut parser, ":foo bar.baz <C-w>");
+        assert_eq!(command(&parser), Some(("foo bar.".into(), 8)));
+        parse_keys(&mut parser, "<C-w>");
+        assert_eq!(command(&parser), Some(("foo bar".into(), 7)));
+        parse_keys(&mut parser, "<Left><Left><C-u>");
+        assert_eq!(command(&parser), Some(("ar".into(), 0)));
+    }
+
+    #[test]
+    fn completion() {
+        let command = |parser: &ViParser| match &parser.mode {
+            ViMode::Command { value, .. } | ViMode::Search { value, .. } => value.clone(),
+            _ => String::new(),
+        };
+        let candidates = || vec!["foo".into(), "far".into()];
+
+        let mut parser = ViParser::new();
+        let events = parse_keys(&mut parser, ":e f<Tab>");
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::Complete { value, cursor: 3, search: false } if value == "e f"
+        )));
+        parser.set_completion(2, candidates(), |_| {});
+        assert_eq!(command(&parser), "e foo");
+        let mut values = Vec::new();
+        for _ in 0..3 {
+            parse_keys(&mut parser, "<Tab>");
+            values.push(command(&parser));
+        }
+        assert_eq!(values, ["e far", "e f", "e foo"]);
+        parse_keys(&mut parser, "<S-Tab>");
+        assert_eq!(command(&parser), "e f");
+
+        // Shift-Tab starts at the last candidate
+        let mut parser = ViParser::new();
+        parse_keys(&mut parser, ":e f<S-Tab>");
+        parser.set_completion(2, candidates(), |_| {});
+        assert_eq!(command(&parser), "e far");
+        parse_keys(&mut parser, "<S-Tab>");
+        assert_eq!(command(&parser), "e foo");
+
+        // Completing a search previews it
+        let mut parser = ViParser::new();
+        parse_keys(&mut parser, "/f<Tab>");
+        let mut events = Vec::new();
+        parser.set_completion(0, candidates(), |event| events.push(event));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::SearchPreview(request) if request.pattern == "foo"
+        )));
+    }
+
+    #[test]
+    fn write_to_command() {
+        let writes = |keys| {
+            parse_keys(&mut ViParser::new(), keys)
+                .iter()
+                .filter(|event| matches!(event, Event::Write(_) | Event::WriteQuit(_)))
+                .count()
+        };
+        assert_eq!(writes(":w !sudo tee %<CR>"), 0);
+        assert_eq!(writes(":wq !cmd<CR>"), 0);
+        assert_eq!(writes(":w file<CR>"), 1);
+    }
+
+    #[test]
+    fn ex_range() {
+        let ex = |keys| {
+            parse_keys(&mut ViParser::new(), keys)
+                .into_iter()
+                .find_map(|event| match event {
+                    Event::Ex {
+                        range,
+                        name,
+                        bang: false,
+                        args,
+                    } => Some((range, name, args)),
+                    _ => None,
+                })
+        };
+        let address = |line| ExAddress { line, offset: 0 };
+        let range = |start, end| ExRange {
+            start: address(start),
+            end: Some(address(end)),
+        };
+
+        assert_eq!(
+            ex(":%d<CR>"),
+            Some((Some(ExRange::all()), "d".into(), String::new()))
+        );
+        assert_eq!(
+            ex(":.,$y<CR>"),
+            Some((
+                Some(range(ExLine::Current, ExLine::Last)),
+                "y".into(),
+                String::new()
+            ))
+        );
+        assert_eq!(
+            ex(":'<,'>s/a/b/g<CR>"),
+            Some((
+                Some(range(ExLine::Mark('<'), ExLine::Mark('>'))),
+                "s".into(),
+                "/a/b/g".into()
+            ))
+        );
+        assert_eq!(
+            ex(":5<CR>"),
+            Some((
+                Some(ExRange {
+                    start: address(ExLine::Number(5)),
+                    end: None
+                }),
+                String::new(),
+                String::new()
+            ))
+        );
+        assert_eq!(ex(":<CR>"), None);
+
+        // Commands run by the parser keep their range or count
+        let events = parse_keys(&mut ViParser::new(), ":.,$w part<CR>");
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::Write(WriteRequest { range: Some(range), path: Some(path), .. })
+                if *range == ExRange {
+                    start: address(ExLine::Current),
+                    end: Some(address(ExLine::Last)),
+                } && path == "part"
+        )));
+        let events = parse_keys(&mut ViParser::new(), ":3b<CR>");
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::Buffer(BufferCommand::Number(3)))));
+        assert!(!events.iter().any(|event| matches!(event, Event::Ex { .. })));
     }
 }